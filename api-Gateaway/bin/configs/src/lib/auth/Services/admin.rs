@@ -0,0 +1,319 @@
+use hyper::{Method, StatusCode};
+use hyper::header::AUTHORIZATION;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use crate::models::{ApiRequest, ApiResponse, config::AdminConfig};
+use crate::models::response::ErrorResponse;
+use crate::rate_limiting::overrides::{OverrideError, OverrideStore, RateLimitOverride};
+use crate::rate_limiting::policy::RateLimitPolicies;
+use crate::rate_limiting::quota::{QuotaError, QuotaManager, QuotaPlan};
+use crate::services::cache::{CachePurge, CacheService};
+
+const DEFAULT_TOP_KEYS: usize = 20;
+const MAX_TOP_KEYS: usize = 1000;
+
+// Operational endpoints served by the gateway itself under `prefix`
+pub struct AdminApi {
+    prefix: String,
+    tokens: Vec<String>,
+    quotas: Option<Arc<QuotaManager>>,
+    rate_limits: Option<Arc<RateLimitPolicies>>,
+    // Shares override edits with other instances through Redis
+    overrides: Option<Arc<OverrideStore>>,
+    cache: Option<Arc<CacheService>>,
+}
+
+impl AdminApi {
+    pub fn new(config: &AdminConfig) -> Self {
+        Self {
+            prefix: config.prefix.trim_end_matches('/').to_string(),
+            tokens: config.tokens_sha256.iter().map(|t| t.to_ascii_lowercase()).collect(),
+            quotas: None,
+            rate_limits: None,
+            overrides: None,
+            cache: None,
+        }
+    }
+
+    pub fn with_quotas(mut self, quotas: Arc<QuotaManager>) -> Self {
+        self.quotas = Some(quotas);
+        self
+    }
+
+    pub fn with_rate_limit_policies(mut self, policies: Arc<RateLimitPolicies>) -> Self {
+        self.rate_limits = Some(policies);
+        self
+    }
+
+    pub fn with_rate_limit_overrides(mut self, overrides: Arc<OverrideStore>) -> Self {
+        self.overrides = Some(overrides);
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Arc<CacheService>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(self.prefix.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    fn authorized(&self, req: &ApiRequest) -> bool {
+        let Some(token) = req.headers.get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+
+        let digest = hex::encode(Sha256::digest(token.trim().as_bytes()));
+        self.tokens.iter().any(|t| *t == digest)
+    }
+
+    pub async fn handle(&self, req: &ApiRequest) -> ApiResponse {
+        if !self.authorized(req) {
+            return error(StatusCode::UNAUTHORIZED, "Admin token required");
+        }
+
+        let path = req.uri.path()[self.prefix.len()..].trim_matches('/');
+        let segments: Vec<&str> = path.split('/').collect();
+
+        match (&req.method, segments.as_slice()) {
+            (&Method::GET, ["quotas", consumer]) => self.quota_usage(consumer).await,
+            (&Method::DELETE, ["quotas", consumer]) => self.reset_quota(consumer).await,
+            (&Method::PUT, ["quotas", "plans", name]) => self.put_plan(name, req).await,
+            (&Method::PUT, ["quotas", "consumers", consumer]) => self.assign_plan(consumer, req).await,
+            (&Method::DELETE, ["quotas", "consumers", consumer]) => self.unassign_plan(consumer).await,
+            (&Method::GET, ["rate-limits", "overrides"]) => self.list_overrides(),
+            (&Method::PUT, ["rate-limits", "overrides", name]) => self.put_override(name, req).await,
+            (&Method::DELETE, ["rate-limits", "overrides", name]) => self.delete_override(name).await,
+            (&Method::POST, ["cache", "purge"]) => self.purge_cache(req).await,
+            (&Method::GET, ["cache", "keys"]) => self.top_cache_keys(req),
+            _ => error(StatusCode::NOT_FOUND, "Unknown admin endpoint"),
+        }
+    }
+
+    async fn quota_usage(&self, consumer: &str) -> ApiResponse {
+        let Some(quotas) = &self.quotas else {
+            return error(StatusCode::NOT_FOUND, "Quotas are not configured");
+        };
+
+        match quotas.usage(consumer).await {
+            Ok(Some(usage)) => ok(&usage),
+            Ok(None) => error(StatusCode::NOT_FOUND, "Consumer has no quota plan"),
+            Err(e) => {
+                log::error!("Cannot read quota usage of {}: {}", consumer, e);
+                error(StatusCode::SERVICE_UNAVAILABLE, "Quota store unavailable")
+            }
+        }
+    }
+
+    async fn reset_quota(&self, consumer: &str) -> ApiResponse {
+        let Some(quotas) = &self.quotas else {
+            return error(StatusCode::NOT_FOUND, "Quotas are not configured");
+        };
+
+        match quotas.reset(consumer).await {
+            Ok(()) => {
+                log::info!("Quota usage of {} reset by admin", consumer);
+                ApiResponse::new(StatusCode::NO_CONTENT)
+            }
+            Err(e) => {
+                log::error!("Cannot reset quota usage of {}: {}", consumer, e);
+                error(StatusCode::SERVICE_UNAVAILABLE, "Quota store unavailable")
+            }
+        }
+    }
+
+    // Plans are stored in Redis, other instances pick up changes on their next sync
+    async fn put_plan(&self, name: &str, req: &ApiRequest) -> ApiResponse {
+        let Some(quotas) = &self.quotas else {
+            return error(StatusCode::NOT_FOUND, "Quotas are not configured");
+        };
+
+        let plan: QuotaPlan = match serde_json::from_slice(&req.body) {
+            Ok(plan) => plan,
+            Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid plan: {}", e)),
+        };
+
+        match quotas.put_plan(name, plan).await {
+            Ok(()) => {
+                log::info!("Quota plan {} updated by admin", name);
+                ApiResponse::new(StatusCode::NO_CONTENT)
+            }
+            Err(e) => quota_store_error(e),
+        }
+    }
+
+    // Body: {"plan": "<name>"}
+    async fn assign_plan(&self, consumer: &str, req: &ApiRequest) -> ApiResponse {
+        #[derive(Deserialize)]
+        struct Assignment {
+            plan: String,
+        }
+
+        let Some(quotas) = &self.quotas else {
+            return error(StatusCode::NOT_FOUND, "Quotas are not configured");
+        };
+
+        let assignment: Assignment = match serde_json::from_slice(&req.body) {
+            Ok(assignment) => assignment,
+            Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid assignment: {}", e)),
+        };
+
+        match quotas.assign(consumer, Some(&assignment.plan)).await {
+            Ok(()) => {
+                log::info!("Consumer {} moved to quota plan {} by admin", consumer, assignment.plan);
+                ApiResponse::new(StatusCode::NO_CONTENT)
+            }
+            Err(e) => quota_store_error(e),
+        }
+    }
+
+    async fn unassign_plan(&self, consumer: &str) -> ApiResponse {
+        let Some(quotas) = &self.quotas else {
+            return error(StatusCode::NOT_FOUND, "Quotas are not configured");
+        };
+
+        match quotas.assign(consumer, None).await {
+            Ok(()) => {
+                log::info!("Consumer {} moved back to the default quota plan by admin", consumer);
+                ApiResponse::new(StatusCode::NO_CONTENT)
+            }
+            Err(e) => quota_store_error(e),
+        }
+    }
+
+    // Edits are stored in Redis and applied on every instance; without Redis they
+    // only apply to this instance until the next restart
+    fn list_overrides(&self) -> ApiResponse {
+        match &self.rate_limits {
+            Some(policies) => ok(&policies.overrides()),
+            None => error(StatusCode::NOT_FOUND, "Rate limit policies are not configured"),
+        }
+    }
+
+    async fn put_override(&self, name: &str, req: &ApiRequest) -> ApiResponse {
+        let Some(policies) = &self.rate_limits else {
+            return error(StatusCode::NOT_FOUND, "Rate limit policies are not configured");
+        };
+
+        let mut rule: RateLimitOverride = match serde_json::from_slice(&req.body) {
+            Ok(rule) => rule,
+            Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid override: {}", e)),
+        };
+        rule.name = name.to_string();
+
+        let result = match &self.overrides {
+            Some(store) => store.put(rule).await,
+            None => policies.upsert_override(rule).map_err(OverrideError::from),
+        };
+        match result {
+            Ok(()) => {
+                log::info!("Rate limit override {} updated by admin", name);
+                ApiResponse::new(StatusCode::NO_CONTENT)
+            }
+            Err(e) => override_store_error(e),
+        }
+    }
+
+    async fn delete_override(&self, name: &str) -> ApiResponse {
+        let Some(policies) = &self.rate_limits else {
+            return error(StatusCode::NOT_FOUND, "Rate limit policies are not configured");
+        };
+
+        let result = match &self.overrides {
+            Some(store) => store.remove(name).await,
+            None if policies.remove_override(name) => Ok(()),
+            None => Err(OverrideError::NotFound(name.to_string())),
+        };
+        match result {
+            Ok(()) => {
+                log::info!("Rate limit override {} removed by admin", name);
+                ApiResponse::new(StatusCode::NO_CONTENT)
+            }
+            Err(e) => override_store_error(e),
+        }
+    }
+
+    // Body: {"tags": [...], "urls": [...], "prefixes": [...]}; other instances purge via pub/sub
+    async fn purge_cache(&self, req: &ApiRequest) -> ApiResponse {
+        let Some(cache) = &self.cache else {
+            return error(StatusCode::NOT_FOUND, "Response cache is not enabled");
+        };
+
+        let purge = match serde_json::from_slice::<CachePurge>(&req.body) {
+            Ok(purge) if !purge.is_empty() => purge.normalized(),
+            Ok(_) => return error(StatusCode::BAD_REQUEST, "Nothing to purge"),
+            Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid purge: {}", e)),
+        };
+
+        match cache.purge(&purge).await {
+            Ok(removed) => {
+                log::info!("Cache purge by admin removed {} shared entries: {:?}", removed, purge);
+                ok(&serde_json::json!({ "removed": removed }))
+            }
+            Err(e) => {
+                log::error!("Cache purge failed: {}", e);
+                error(StatusCode::SERVICE_UNAVAILABLE, "Shared cache unavailable; local entries were purged")
+            }
+        }
+    }
+
+    // Most requested keys of this instance's local tier: `?limit=N`
+    fn top_cache_keys(&self, req: &ApiRequest) -> ApiResponse {
+        let Some(cache) = &self.cache else {
+            return error(StatusCode::NOT_FOUND, "Response cache is not enabled");
+        };
+
+        let limit = req.uri.query()
+            .unwrap_or_default()
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("limit="))
+            .map(str::parse::<usize>);
+        let limit = match limit {
+            None => DEFAULT_TOP_KEYS,
+            Some(Ok(limit)) => limit.min(MAX_TOP_KEYS),
+            Some(Err(_)) => return error(StatusCode::BAD_REQUEST, "Invalid limit"),
+        };
+        ok(&cache.top_keys(limit))
+    }
+}
+
+fn ok<T: Serialize>(body: &T) -> ApiResponse {
+    ApiResponse::new(StatusCode::OK).json(body)
+}
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    ApiResponse::new(status).json(&ErrorResponse {
+        error: status.canonical_reason().unwrap_or_default().to_string(),
+        code: status.as_u16(),
+        message: message.to_string(),
+        details: None,
+    })
+}
+
+fn override_store_error(e: OverrideError) -> ApiResponse {
+    match e {
+        OverrideError::Policy(e) => error(StatusCode::UNPROCESSABLE_ENTITY, &e.to_string()),
+        OverrideError::NotFound(_) => error(StatusCode::NOT_FOUND, "Unknown override"),
+        OverrideError::Conflict => error(StatusCode::CONFLICT, "Overrides were edited concurrently, retry"),
+        e => {
+            log::error!("Cannot update rate limit overrides: {}", e);
+            error(StatusCode::SERVICE_UNAVAILABLE, "Rate limit store unavailable")
+        }
+    }
+}
+
+fn quota_store_error(e: QuotaError) -> ApiResponse {
+    match e {
+        QuotaError::UnknownPlan(_, plan) => error(StatusCode::UNPROCESSABLE_ENTITY, &format!("Unknown plan {}", plan)),
+        e => {
+            log::error!("Cannot update quota plans: {}", e);
+            error(StatusCode::SERVICE_UNAVAILABLE, "Quota store unavailable")
+        }
+    }
+}
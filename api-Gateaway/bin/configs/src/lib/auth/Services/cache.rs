@@ -0,0 +1,783 @@
+use futures::StreamExt;
+use hyper::header::HeaderValue;
+use hyper::{HeaderMap, Uri};
+use moka::future::Cache;
+use moka::notification::RemovalCause;
+use moka::Expiry;
+use redis::{AsyncCommands, RedisError, RedisResult, Script};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use crate::models::{ApiResponse, config::{CacheKeyStrategy, CacheTier, RouteCacheConfig}};
+use crate::services::cache_key::{CacheKeyBuilder, CacheKeyError};
+use crate::services::coalescing::{Flight, RequestCoalescer};
+use crate::services::http_cache::{self, CachedResponse};
+use crate::rate_limiting::redis_store::RedisConnection;
+
+const X_CACHE: &str = "x-cache";
+
+// Rewritten, deleted and purged keys are broadcast here so every instance drops its local copies
+const INVALIDATION_CHANNEL: &str = "gateway:cache:invalidate";
+
+// First byte of a value in Redis says how the rest is encoded
+const PLAIN: u8 = 0;
+const ZSTD: u8 = 1;
+const ZSTD_LEVEL: i32 = 3;
+// Decompressed values may exceed the body limit by their headers
+const DECODE_HEADROOM: usize = 64 * 1024;
+
+// Adds a key to its purge index sets, only ever extending their expiry
+const INDEX_KEY: &str = r"
+for _, index in ipairs(KEYS) do
+    redis.call('SADD', index, ARGV[1])
+    if redis.call('TTL', index) < tonumber(ARGV[2]) then
+        redis.call('EXPIRE', index, ARGV[2])
+    end
+end
+return 0
+";
+
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error("Redis error: {0}")]
+    Redis(#[from] RedisError),
+    #[error("Invalid invalidation message: {0}")]
+    Message(#[from] serde_json::Error),
+    #[error("Cannot encode cache entry: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("Cannot compress cache entry: {0}")]
+    Compression(#[from] std::io::Error),
+    #[error("Unknown cache value encoding")]
+    UnknownEncoding,
+}
+
+// How a response relates to the cache, reported in X-Cache on routes that ask for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    Stale,
+    // The request could not be answered from the cache, e.g. a POST
+    Bypass,
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Stale => "STALE",
+            CacheStatus::Bypass => "BYPASS",
+        }
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        headers.insert(X_CACHE, HeaderValue::from_static(self.as_str()));
+    }
+}
+
+// A route's view of the cache: where its entries live and how its metrics are labelled
+#[derive(Debug, Clone, Copy)]
+pub struct CacheScope<'a> {
+    pub route: &'a str,
+    pub tier: CacheTier,
+}
+
+impl CacheScope<'_> {
+    fn record_lookup(&self, tier: &'static str, hit: bool, started: Instant) {
+        let name = if hit { "gateway_cache_hits_total" } else { "gateway_cache_misses_total" };
+        metrics::increment_counter!(name, "route" => self.route.to_string(), "tier" => tier);
+        metrics::histogram!(
+            "gateway_cache_lookup_duration_seconds",
+            started.elapsed(),
+            "route" => self.route.to_string(),
+            "tier" => tier,
+        );
+    }
+
+    // Bytes written; what each tier currently holds is the gateway_cache_stored_bytes gauge
+    fn record_stored(&self, tier: &'static str, bytes: usize) {
+        metrics::counter!("gateway_cache_stored_bytes_total", bytes as u64, "route" => self.route.to_string(), "tier" => tier);
+    }
+}
+
+// Per-key figures of the local tier, for the admin API
+#[derive(Debug, Serialize)]
+pub struct CacheKeyStats {
+    pub key: String,
+    pub path: String,
+    pub bytes: usize,
+    pub hits: u64,
+    pub age: u64,
+    pub ttl: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Invalidation {
+    Keys(Vec<String>),
+    Purge(CachePurge),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InvalidationMessage {
+    // Instances skip their own messages
+    origin: String,
+    invalidation: Invalidation,
+}
+
+// Entries to drop from every tier on every instance
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CachePurge {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Exact paths; every variant (query string, user, Vary) goes
+    #[serde(default)]
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+}
+
+impl CachePurge {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.urls.is_empty() && self.prefixes.is_empty()
+    }
+
+    // Full URLs are accepted for convenience; only their path counts
+    pub fn normalized(mut self) -> Self {
+        let path = |url: String| url.parse::<Uri>().map(|uri| uri.path().to_string()).unwrap_or(url);
+        self.urls = self.urls.into_iter().map(path).collect();
+        self.prefixes = self.prefixes.into_iter().map(path).collect();
+        self
+    }
+
+    fn matches(&self, entry: &CachedResponse) -> bool {
+        self.tags.iter().any(|tag| entry.tags.contains(tag))
+            || self.urls.iter().any(|url| *url == entry.path)
+            || self.prefixes.iter().any(|prefix| entry.path.starts_with(prefix.as_str()))
+    }
+}
+
+fn tag_index(tag: &str) -> String {
+    format!("cache-tag:{}", tag)
+}
+
+fn path_index(path: &str) -> String {
+    format!("cache-path:{}", path)
+}
+
+// Escapes glob characters for SCAN MATCH
+fn glob_escape(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '*' | '?' | '[' | ']' | '\\' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect()
+}
+
+fn encode(entry: &CachedResponse, compress_min_bytes: usize) -> Result<Vec<u8>, CacheError> {
+    let data = bincode::serialize(entry)?;
+    if data.len() < compress_min_bytes {
+        return Ok([&[PLAIN][..], &data].concat());
+    }
+    let mut encoded = vec![ZSTD];
+    encoded.extend(zstd::bulk::compress(&data, ZSTD_LEVEL)?);
+    Ok(encoded)
+}
+
+fn decode(data: &[u8], max_bytes: usize) -> Result<CachedResponse, CacheError> {
+    match data.split_first() {
+        Some((&PLAIN, data)) => Ok(bincode::deserialize(data)?),
+        Some((&ZSTD, data)) => Ok(bincode::deserialize(&zstd::bulk::decompress(data, max_bytes)?)?),
+        _ => Err(CacheError::UnknownEncoding),
+    }
+}
+
+// A local copy lives no longer than the entry it was taken from
+#[derive(Clone)]
+struct LocalEntry {
+    response: Arc<CachedResponse>,
+    ttl: Duration,
+    hits: Arc<AtomicU64>,
+    // Labels the entry's eviction and size metrics
+    route: String,
+}
+
+// Size of the local tier's entries per route, reported as gateway_cache_stored_bytes
+#[derive(Default)]
+struct StoredBytes(Mutex<HashMap<String, u64>>);
+
+impl StoredBytes {
+    fn add(&self, route: &str, bytes: u64) {
+        self.update(route, |stored| stored + bytes);
+    }
+
+    fn remove(&self, route: &str, bytes: u64) {
+        self.update(route, |stored| stored.saturating_sub(bytes));
+    }
+
+    fn update(&self, route: &str, change: impl FnOnce(u64) -> u64) {
+        let Ok(mut routes) = self.0.lock() else { return };
+        let stored = routes.entry(route.to_string()).or_default();
+        *stored = change(*stored);
+        metrics::gauge!("gateway_cache_stored_bytes", *stored as f64, "route" => route.to_string(), "tier" => "local");
+    }
+}
+
+struct EntryExpiry;
+
+impl Expiry<String, LocalEntry> for EntryExpiry {
+    fn expire_after_create(&self, _key: &String, entry: &LocalEntry, _created_at: Instant) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &LocalEntry,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+// Held while a key is refreshed in the background
+pub struct RefreshGuard {
+    refreshing: Arc<Mutex<HashSet<String>>>,
+    key: String,
+}
+
+impl Drop for RefreshGuard {
+    fn drop(&mut self) {
+        if let Ok(mut refreshing) = self.refreshing.lock() {
+            refreshing.remove(&self.key);
+        }
+    }
+}
+
+pub struct CacheService {
+    local_cache: Cache<String, LocalEntry>,
+    // Vary header names last seen for each primary key
+    vary_index: Cache<String, Arc<Vec<String>>>,
+    local_bytes: Arc<StoredBytes>,
+    redis: Option<RedisConnection>,
+    instance_id: String,
+    // Minimum time entries are kept; stale ones stay around for revalidation
+    ttl: Duration,
+    refreshing: Arc<Mutex<HashSet<String>>>,
+    max_object_bytes: usize,
+    compress_min_bytes: usize,
+    max_shared_value_bytes: usize,
+    excluded_statuses: Vec<u16>,
+    key_strategy: CacheKeyStrategy,
+    ignored_query_params: Vec<String>,
+    coalescer: Option<RequestCoalescer>,
+}
+
+impl CacheService {
+    pub fn new(local_cache_size: u64, ttl: Duration, redis: Option<RedisConnection>) -> Self {
+        let local_bytes = Arc::new(StoredBytes::default());
+        let removed_bytes = local_bytes.clone();
+        let local_cache = Cache::builder()
+            .max_capacity(local_cache_size)
+            .expire_after(EntryExpiry)
+            .support_invalidation_closures()
+            .eviction_listener(move |_, entry: LocalEntry, cause| {
+                removed_bytes.remove(&entry.route, entry.response.size() as u64);
+
+                let cause = match cause {
+                    RemovalCause::Size => "size",
+                    RemovalCause::Expired => "expired",
+                    // Replaced, invalidated or purged on purpose
+                    RemovalCause::Explicit | RemovalCause::Replaced => return,
+                };
+                metrics::increment_counter!("gateway_cache_evictions_total", "route" => entry.route, "tier" => "local", "cause" => cause);
+            })
+            .build();
+        let vary_index = Cache::builder()
+            .max_capacity(local_cache_size)
+            .time_to_live(ttl)
+            .build();
+
+        Self {
+            local_cache,
+            vary_index,
+            local_bytes,
+            redis,
+            instance_id: uuid::Uuid::new_v4().to_string(),
+            ttl,
+            refreshing: Arc::default(),
+            max_object_bytes: 1024 * 1024,
+            compress_min_bytes: 1024,
+            max_shared_value_bytes: 512 * 1024,
+            excluded_statuses: Vec::new(),
+            key_strategy: CacheKeyStrategy::FullUrl,
+            ignored_query_params: Vec::new(),
+            coalescer: None,
+        }
+    }
+
+    pub fn with_max_object_bytes(mut self, max_object_bytes: usize) -> Self {
+        self.max_object_bytes = max_object_bytes;
+        self
+    }
+
+    pub fn with_shared_value_limits(mut self, compress_min_bytes: usize, max_shared_value_bytes: usize) -> Self {
+        self.compress_min_bytes = compress_min_bytes;
+        self.max_shared_value_bytes = max_shared_value_bytes;
+        self
+    }
+
+    pub fn with_excluded_statuses(mut self, statuses: Vec<u16>) -> Self {
+        self.excluded_statuses = statuses;
+        self
+    }
+
+    pub fn with_key_strategy(mut self, strategy: CacheKeyStrategy, ignored_query_params: Vec<String>) -> Self {
+        self.key_strategy = strategy;
+        self.ignored_query_params = ignored_query_params;
+        self
+    }
+
+    pub fn with_coalescing(mut self, coalescer: RequestCoalescer) -> Self {
+        self.coalescer = Some(coalescer);
+        self
+    }
+
+    pub fn redis(&self) -> Option<RedisConnection> {
+        self.redis.clone()
+    }
+
+    // Keys for a route, using its own strategy when it has one
+    pub fn key_builder(&self, config: &RouteCacheConfig) -> Result<CacheKeyBuilder, CacheKeyError> {
+        let strategy = config.key.as_ref().unwrap_or(&self.key_strategy);
+        CacheKeyBuilder::new(strategy, config.shared, self.ignored_query_params.clone())
+    }
+
+    pub async fn get(&self, key: &str, scope: CacheScope<'_>) -> Option<Arc<CachedResponse>> {
+        let started = Instant::now();
+        if scope.tier != CacheTier::Redis {
+            if let Some(local) = self.local_cache.get(key).await {
+                local.hits.fetch_add(1, Ordering::Relaxed);
+                scope.record_lookup("local", true, started);
+                return Some(local.response);
+            }
+            // Counted even when Redis then has the entry
+            scope.record_lookup("local", false, started);
+        }
+
+        let Some(conn) = self.redis.clone().filter(|_| scope.tier != CacheTier::Local) else {
+            // A Redis-only route without Redis still counts as a Redis miss
+            if scope.tier == CacheTier::Redis {
+                scope.record_lookup("redis", false, started);
+            }
+            return None;
+        };
+        let started = Instant::now();
+        let entry = self.get_shared(conn, key, scope).await;
+        scope.record_lookup("redis", entry.is_some(), started);
+        entry
+    }
+
+    async fn get_shared(&self, mut conn: RedisConnection, key: &str, scope: CacheScope<'_>) -> Option<Arc<CachedResponse>> {
+        let (data, ttl_ms): (Option<Vec<u8>>, i64) = redis::pipe()
+            .get(key)
+            .pttl(key)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| log::warn!("Cache read of {} failed: {}", key, e))
+            .ok()?;
+        let entry = decode(&data?, self.max_object_bytes + DECODE_HEADROOM)
+            .map_err(|e| log::warn!("Dropping undecodable cache entry {}: {}", key, e))
+            .ok()?;
+
+        // The local copy expires together with the shared one
+        let entry = Arc::new(entry);
+        if scope.tier == CacheTier::Both && ttl_ms > 0 {
+            self.insert_local(key, entry.clone(), Duration::from_millis(ttl_ms as u64), scope.route).await;
+        }
+        Some(entry)
+    }
+
+    pub async fn set(&self, key: &str, entry: Arc<CachedResponse>, scope: CacheScope<'_>) {
+        let retention = self.retention(&entry);
+        if scope.tier != CacheTier::Redis {
+            scope.record_stored("local", entry.size());
+            self.insert_local(key, entry.clone(), retention, scope.route).await;
+        }
+
+        let Some(mut conn) = self.redis.clone().filter(|_| scope.tier != CacheTier::Local) else { return };
+        let data = match encode(&entry, self.compress_min_bytes) {
+            Ok(data) => data,
+            Err(e) => return log::warn!("Cannot encode cache entry {}: {}", key, e),
+        };
+        if data.len() > self.max_shared_value_bytes {
+            return log::debug!("Keeping {} out of Redis: {} bytes encoded", key, data.len());
+        }
+
+        let bytes = data.len();
+        let result: RedisResult<()> = conn.set_ex(key, data, retention.as_secs() as usize).await;
+        match result {
+            Ok(()) => {
+                scope.record_stored("redis", bytes);
+                self.publish(Invalidation::Keys(vec![key.to_string()])).await;
+            }
+            Err(e) => log::warn!("Cache write of {} failed: {}", key, e),
+        }
+    }
+
+    pub async fn invalidate(&self, key: &str) {
+        self.local_cache.invalidate(key).await;
+        if let Some(mut conn) = self.redis.clone() {
+            let result: RedisResult<()> = conn.del(key).await;
+            if let Err(e) = result {
+                log::warn!("Cache invalidation of {} failed: {}", key, e);
+            }
+            self.publish(Invalidation::Keys(vec![key.to_string()])).await;
+        }
+    }
+
+    async fn insert_local(&self, key: &str, response: Arc<CachedResponse>, ttl: Duration, route: &str) {
+        self.local_bytes.add(route, response.size() as u64);
+
+        let hits = Arc::default();
+        let route = route.to_string();
+        self.local_cache.insert(key.to_string(), LocalEntry { response, ttl, hits, route }).await;
+    }
+
+    // Most requested keys of this instance's local tier; Redis-only routes never show up
+    pub fn top_keys(&self, limit: usize) -> Vec<CacheKeyStats> {
+        let now = http_cache::now();
+        let mut keys: Vec<CacheKeyStats> = self.local_cache.iter()
+            .map(|(key, local)| CacheKeyStats {
+                key: key.to_string(),
+                path: local.response.path.clone(),
+                bytes: local.response.size(),
+                hits: local.hits.load(Ordering::Relaxed),
+                age: local.response.age(now),
+                ttl: local.response.ttl,
+            })
+            .collect();
+        keys.sort_unstable_by(|a, b| b.hits.cmp(&a.hits).then(b.bytes.cmp(&a.bytes)));
+        keys.truncate(limit);
+        keys
+    }
+
+    // Stored response for the request's variant of `key`
+    pub async fn lookup(&self, key: &str, request_headers: &HeaderMap, scope: CacheScope<'_>) -> Option<Arc<CachedResponse>> {
+        let vary = self.vary_for(key, scope.tier).await;
+        self.get(&Self::variant_key(key, &vary, request_headers), scope).await
+    }
+
+    pub async fn store(&self, key: &str, path: &str, request_headers: &HeaderMap, mut entry: CachedResponse, scope: CacheScope<'_>) {
+        entry.path = path.to_string();
+        let shared = self.redis.clone().filter(|_| scope.tier != CacheTier::Local);
+        if !entry.vary.is_empty() {
+            let vary = Arc::new(entry.vary.clone());
+            self.vary_index.insert(key.to_string(), vary).await;
+            if let Some(mut conn) = shared.clone() {
+                let expire = self.retention(&entry).as_secs() as usize;
+                let result: RedisResult<()> = conn.set_ex(format!("{}|vary", key), entry.vary.join(","), expire).await;
+                if let Err(e) = result {
+                    log::warn!("Cache write of {} failed: {}", key, e);
+                }
+            }
+        }
+
+        let variant = Self::variant_key(key, &entry.vary, request_headers);
+        if let Some(conn) = shared {
+            if let Err(e) = self.index(conn, &variant, &entry).await {
+                log::warn!("Cache index update of {} failed: {}", variant, e);
+            }
+        }
+        self.set(&variant, Arc::new(entry), scope).await;
+    }
+
+    // Records the key under its tags and path so purges can find it in Redis
+    async fn index(&self, mut conn: RedisConnection, key: &str, entry: &CachedResponse) -> RedisResult<()> {
+        let script = Script::new(INDEX_KEY);
+        let mut invocation = script.prepare_invoke();
+        invocation.key(path_index(&entry.path));
+        for tag in &entry.tags {
+            invocation.key(tag_index(tag));
+        }
+        invocation.arg(key).arg(self.retention(entry).as_secs());
+        invocation.invoke_async(&mut conn).await
+    }
+
+    // Drops matching entries from both tiers and has every other instance do the same
+    pub async fn purge(&self, purge: &CachePurge) -> Result<usize, CacheError> {
+        self.purge_local(purge);
+
+        let Some(mut conn) = self.redis.clone() else { return Ok(0) };
+        let removed = self.purge_redis(&mut conn, purge).await?;
+        self.try_publish(Invalidation::Purge(purge.clone())).await?;
+        Ok(removed)
+    }
+
+    async fn publish(&self, invalidation: Invalidation) {
+        if let Err(e) = self.try_publish(invalidation).await {
+            log::warn!("Cannot broadcast cache invalidation: {}", e);
+        }
+    }
+
+    async fn try_publish(&self, invalidation: Invalidation) -> Result<(), CacheError> {
+        let Some(mut conn) = self.redis.clone() else { return Ok(()) };
+        let message = serde_json::to_string(&InvalidationMessage {
+            origin: self.instance_id.clone(),
+            invalidation,
+        })?;
+        let _: () = conn.publish(INVALIDATION_CHANNEL, message).await?;
+        Ok(())
+    }
+
+    fn purge_local(&self, purge: &CachePurge) {
+        let purge = purge.clone();
+        if let Err(e) = self.local_cache.invalidate_entries_if(move |_, entry| purge.matches(&entry.response)) {
+            log::error!("Local cache purge failed: {}", e);
+        }
+    }
+
+    async fn purge_redis(&self, conn: &mut RedisConnection, purge: &CachePurge) -> RedisResult<usize> {
+        let mut indexes: Vec<String> = purge.tags.iter().map(|tag| tag_index(tag))
+            .chain(purge.urls.iter().map(|url| path_index(url)))
+            .collect();
+        for prefix in &purge.prefixes {
+            let mut iter = conn.scan_match::<_, String>(format!("{}*", glob_escape(&path_index(prefix)))).await?;
+            while let Some(index) = iter.next_item().await {
+                indexes.push(index);
+            }
+        }
+
+        let mut removed = 0;
+        for index in indexes {
+            let keys: Vec<String> = conn.smembers(&index).await?;
+            if !keys.is_empty() {
+                removed += conn.del::<_, usize>(&keys).await?;
+            }
+            let _: () = conn.del(&index).await?;
+        }
+        Ok(removed)
+    }
+
+    // Drops local copies that other instances rewrote, deleted or purged, until the process exits
+    pub async fn listen_for_invalidations(self: Arc<Self>, client: redis::Client) {
+        loop {
+            if let Err(e) = self.apply_invalidations(&client).await {
+                log::warn!("Cache invalidation subscription lost, resubscribing: {}", e);
+            }
+            // Anything missed meanwhile may be stale
+            self.local_cache.invalidate_all();
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn apply_invalidations(&self, client: &redis::Client) -> Result<(), CacheError> {
+        let mut pubsub = client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(INVALIDATION_CHANNEL).await?;
+
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let payload: String = message.get_payload()?;
+            let message = match serde_json::from_str::<InvalidationMessage>(&payload) {
+                Ok(message) => message,
+                Err(e) => {
+                    log::warn!("Ignoring cache invalidation message: {}", e);
+                    continue;
+                }
+            };
+            if message.origin == self.instance_id {
+                continue;
+            }
+            match message.invalidation {
+                Invalidation::Keys(keys) => {
+                    for key in keys {
+                        self.local_cache.invalidate(&key).await;
+                    }
+                }
+                Invalidation::Purge(purge) => self.purge_local(&purge),
+            }
+        }
+        Ok(())
+    }
+
+    // Waits while another request for `key` is being fetched; hold the flight until the response is stored
+    pub async fn coalesce(&self, key: &str) -> Flight {
+        match &self.coalescer {
+            Some(coalescer) => coalescer.join(key).await,
+            None => Flight::default(),
+        }
+    }
+
+    // None when another task is already refreshing `key`
+    pub fn begin_refresh(&self, key: &str) -> Option<RefreshGuard> {
+        let mut refreshing = self.refreshing.lock().ok()?;
+        if !refreshing.insert(key.to_string()) {
+            return None;
+        }
+        Some(RefreshGuard { refreshing: self.refreshing.clone(), key: key.to_string() })
+    }
+
+    // Entries last until their hard TTL, but at least as long as the configured TTL
+    fn retention(&self, entry: &CachedResponse) -> Duration {
+        Duration::from_secs(entry.expires_in(http_cache::now()).max(self.ttl.as_secs()).max(1))
+    }
+
+    // Buffers a response worth storing; None leaves it untouched
+    pub async fn capture(
+        &self,
+        request_headers: &HeaderMap,
+        res: &mut ApiResponse,
+        default_ttl: Option<u64>,
+    ) -> Result<Option<CachedResponse>, hyper::Error> {
+        if self.excluded_statuses.contains(&res.status.as_u16()) {
+            return Ok(None);
+        }
+        CachedResponse::capture(request_headers, res, default_ttl, self.max_object_bytes).await
+    }
+
+    async fn vary_for(&self, key: &str, tier: CacheTier) -> Arc<Vec<String>> {
+        if let Some(vary) = self.vary_index.get(key).await {
+            return vary;
+        }
+
+        let Some(mut conn) = self.redis.clone().filter(|_| tier != CacheTier::Local) else { return Arc::default() };
+        let names: Option<String> = conn.get(format!("{}|vary", key)).await.unwrap_or_default();
+        let vary = Arc::new(names
+            .map(|names| names.split(',').map(str::to_string).collect())
+            .unwrap_or_default());
+        self.vary_index.insert(key.to_string(), vary.clone()).await;
+        vary
+    }
+
+    // Each combination of the varying request headers is stored separately
+    fn variant_key(key: &str, vary: &[String], request_headers: &HeaderMap) -> String {
+        if vary.is_empty() {
+            return key.to_string();
+        }
+
+        let values: Vec<u8> = vary.iter()
+            .flat_map(|name| {
+                let mut line = name.as_bytes().to_vec();
+                line.push(b'=');
+                for value in request_headers.get_all(name.as_str()) {
+                    line.extend_from_slice(value.as_bytes());
+                    line.push(b',');
+                }
+                line.push(b'\n');
+                line
+            })
+            .collect();
+        format!("{}|{:x}", key, md5::compute(values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(tier: CacheTier) -> CacheScope<'static> {
+        CacheScope { route: "/test", tier }
+    }
+
+    fn entry(path: &str, tags: &[&str]) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: Vec::new(),
+            stored_at: http_cache::now(),
+            initial_age: 0,
+            ttl: 60,
+            stale_while_revalidate: None,
+            stale_if_error: None,
+            vary: Vec::new(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_purge_matching() {
+        let purge = CachePurge {
+            tags: vec!["product-42".into()],
+            urls: vec!["https://api.example.com/users?page=2".into()],
+            prefixes: vec!["/exports/".into()],
+        }
+        .normalized();
+        assert_eq!(purge.urls, vec!["/users"]);
+
+        assert!(purge.matches(&entry("/products/shoe", &["catalog", "product-42"])));
+        assert!(purge.matches(&entry("/users", &[])));
+        assert!(purge.matches(&entry("/exports/2024/01", &[])));
+        assert!(!purge.matches(&entry("/users/7", &["catalog"])));
+
+        assert_eq!(glob_escape("cache-path:/a*b?"), "cache-path:/a\\*b\\?");
+    }
+
+    #[tokio::test]
+    async fn test_local_purge() {
+        let cache = CacheService::new(100, Duration::from_secs(60), None);
+        let headers = HeaderMap::new();
+        cache.store("GET:/products/shoe", "/products/shoe", &headers, entry("", &["product-42"]), scope(CacheTier::Both)).await;
+        cache.store("GET:/users", "/users", &headers, entry("", &[]), scope(CacheTier::Both)).await;
+
+        let purge = CachePurge { tags: vec!["product-42".into()], ..Default::default() };
+        assert_eq!(cache.purge(&purge).await.unwrap(), 0);
+        cache.local_cache.run_pending_tasks().await;
+
+        assert!(cache.get("GET:/products/shoe", scope(CacheTier::Both)).await.is_none());
+        assert!(cache.get("GET:/users", scope(CacheTier::Both)).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_top_keys() {
+        let cache = CacheService::new(100, Duration::from_secs(60), None);
+        let headers = HeaderMap::new();
+        let mut large = entry("", &[]);
+        large.body = vec![0; 4096];
+        cache.store("GET:/products", "/products", &headers, large, scope(CacheTier::Local)).await;
+        cache.store("GET:/users", "/users", &headers, entry("", &[]), scope(CacheTier::Local)).await;
+        for _ in 0..3 {
+            cache.get("GET:/users", scope(CacheTier::Local)).await;
+        }
+        cache.local_cache.run_pending_tasks().await;
+
+        let top = cache.top_keys(10);
+        assert_eq!(top.len(), 2);
+        assert_eq!((top[0].path.as_str(), top[0].hits), ("/users", 3));
+        assert_eq!((top[1].path.as_str(), top[1].bytes), ("/products", 4096));
+        assert_eq!(cache.top_keys(1).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_redis_only_tier_skips_local_copies() {
+        let cache = CacheService::new(100, Duration::from_secs(60), None);
+        cache.store("GET:/users", "/users", &HeaderMap::new(), entry("", &[]), scope(CacheTier::Redis)).await;
+        assert!(cache.get("GET:/users", scope(CacheTier::Both)).await.is_none());
+
+        cache.store("GET:/users", "/users", &HeaderMap::new(), entry("", &[]), scope(CacheTier::Local)).await;
+        assert!(cache.get("GET:/users", scope(CacheTier::Local)).await.is_some());
+    }
+
+    #[test]
+    fn test_shared_values_are_compressed() {
+        let mut large = entry("/exports/1", &[]);
+        large.body = b"row,row,row\n".repeat(1000);
+
+        let encoded = encode(&large, 1024).unwrap();
+        assert_eq!(encoded[0], ZSTD);
+        assert!(encoded.len() < large.body.len() / 10);
+        assert_eq!(decode(&encoded, 64 * 1024).unwrap().body, large.body);
+        // Decompression is bounded
+        assert!(decode(&encoded, 1024).is_err());
+
+        let small = encode(&entry("/users", &[]), 1024).unwrap();
+        assert_eq!(small[0], PLAIN);
+        assert_eq!(decode(&small, 1024).unwrap().path, "/users");
+    }
+}
@@ -0,0 +1,316 @@
+use hyper::header::{AUTHORIZATION, HOST};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use thiserror::Error;
+use crate::models::{ApiRequest, config::CacheKeyStrategy};
+use crate::routing::matcher::Route;
+
+#[derive(Debug, Error)]
+pub enum CacheKeyError {
+    #[error("Unknown cache key variable `${0}`")]
+    UnknownVariable(String),
+    #[error("Cache key template is empty")]
+    EmptyTemplate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Variable {
+    Method,
+    Host,
+    Path,
+    Query,
+    // `$1`..`$9`
+    Capture(String),
+    // `$param_<name>`, a named path parameter
+    Param(String),
+    // `$arg_<name>`, a query parameter
+    Arg(String),
+    // `$http_<name>`, a request header with `_` standing in for `-`
+    Header(String),
+    // `$jwt_<claim>` of the authenticated caller
+    Claim(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+// Turns requests into cache keys for one route
+#[derive(Debug, Clone)]
+pub struct CacheKeyBuilder {
+    segments: Option<Vec<Segment>>,
+    path_only: bool,
+    // Share entries between callers instead of keying them per user
+    shared: bool,
+    ignored_query_params: Vec<String>,
+}
+
+impl CacheKeyBuilder {
+    pub fn new(strategy: &CacheKeyStrategy, shared: bool, ignored_query_params: Vec<String>) -> Result<Self, CacheKeyError> {
+        let segments = match strategy {
+            CacheKeyStrategy::Custom(template) => Some(Self::parse(template)?),
+            _ => None,
+        };
+
+        Ok(Self {
+            segments,
+            path_only: *strategy == CacheKeyStrategy::PathOnly,
+            shared,
+            ignored_query_params,
+        })
+    }
+
+    pub fn build(&self, req: &ApiRequest, route: &Route, params: &HashMap<String, String>) -> String {
+        let method = req.method.as_str();
+        let mut key = match &self.segments {
+            Some(segments) => {
+                let rendered: String = segments.iter()
+                    .map(|segment| match segment {
+                        Segment::Literal(text) => text.clone(),
+                        Segment::Variable(variable) => self.resolve(variable, req, route, params).unwrap_or_default(),
+                    })
+                    .collect();
+                // Templates need not mention the path, so routes sharing one keep apart
+                format!("{}:{}:{}", method, route.path, rendered)
+            }
+            None if self.path_only => format!("{}:{}", method, req.uri.path()),
+            None => {
+                let query = self.normalized_query(req);
+                if query.is_empty() {
+                    format!("{}:{}{}", method, Self::host(req), req.uri.path())
+                } else {
+                    format!("{}:{}{}?{}", method, Self::host(req), req.uri.path(), query)
+                }
+            }
+        };
+
+        // Anything fetched with credentials stays private to whoever presented them
+        if !self.shared {
+            if let Some(scope) = Self::user_scope(req) {
+                key.push_str("|user:");
+                key.push_str(&scope);
+            }
+        }
+        key
+    }
+
+    fn parse(template: &str) -> Result<Vec<Segment>, CacheKeyError> {
+        if template.trim().is_empty() {
+            return Err(CacheKeyError::EmptyTemplate);
+        }
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+
+            // `$1` is a single capture; names run until the first character that can't be part of one
+            let mut name = String::new();
+            match chars.peek() {
+                Some(d) if d.is_ascii_digit() => name.push(chars.next().unwrap_or_default()),
+                _ => while let Some(&n) = chars.peek() {
+                    if !(n.is_ascii_alphanumeric() || n == '_') {
+                        break;
+                    }
+                    name.push(n);
+                    chars.next();
+                },
+            }
+            if name.is_empty() {
+                literal.push('$');
+                continue;
+            }
+
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(Segment::Variable(Self::variable(&name)?));
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(segments)
+    }
+
+    fn variable(name: &str) -> Result<Variable, CacheKeyError> {
+        let prefixed = |prefix: &str| name.strip_prefix(prefix).filter(|rest| !rest.is_empty()).map(str::to_string);
+
+        Ok(match name {
+            "method" => Variable::Method,
+            "host" => Variable::Host,
+            "path" => Variable::Path,
+            "query" => Variable::Query,
+            _ if name.chars().all(|c| c.is_ascii_digit()) => Variable::Capture(name.to_string()),
+            _ => if let Some(param) = prefixed("param_") {
+                Variable::Param(param)
+            } else if let Some(arg) = prefixed("arg_") {
+                Variable::Arg(arg)
+            } else if let Some(header) = prefixed("http_") {
+                Variable::Header(header.replace('_', "-"))
+            } else if let Some(claim) = prefixed("jwt_") {
+                Variable::Claim(claim)
+            } else {
+                return Err(CacheKeyError::UnknownVariable(name.to_string()));
+            },
+        })
+    }
+
+    fn resolve(&self, variable: &Variable, req: &ApiRequest, route: &Route, params: &HashMap<String, String>) -> Option<String> {
+        match variable {
+            Variable::Method => Some(req.method.to_string()),
+            Variable::Host => Some(Self::host(req)),
+            Variable::Path => Some(req.uri.path().to_string()),
+            Variable::Query => Some(self.normalized_query(req)),
+            // Routes without a regex still expose the captures of their rewrite rule
+            Variable::Capture(index) => params.get(index).cloned().or_else(|| {
+                let index: usize = index.parse().ok()?;
+                let captures = route.rewrite.as_ref()?.from.captures(req.uri.path())?;
+                captures.get(index).map(|m| m.as_str().to_string())
+            }),
+            Variable::Param(name) => params.get(name).cloned(),
+            Variable::Arg(name) => Self::query_pairs(req)
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string()),
+            Variable::Header(name) => {
+                let values: Vec<&str> = req.headers.get_all(name.as_str())
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .collect();
+                (!values.is_empty()).then(|| values.join(","))
+            }
+            Variable::Claim(claim) => {
+                let claims = serde_json::to_value(req.identity.as_ref()?).ok()?;
+                match claims.get(claim.as_str())? {
+                    serde_json::Value::String(s) => Some(s.clone()),
+                    other => Some(other.to_string()),
+                }
+            }
+        }
+    }
+
+    fn host(req: &ApiRequest) -> String {
+        req.uri.host()
+            .or_else(|| req.headers.get(HOST).and_then(|h| h.to_str().ok()))
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    }
+
+    fn query_pairs(req: &ApiRequest) -> impl Iterator<Item = (&str, &str)> {
+        req.uri.query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+    }
+
+    // Sorted, without tracking parameters, so equivalent URLs share an entry
+    fn normalized_query(&self, req: &ApiRequest) -> String {
+        let mut pairs: Vec<(&str, &str)> = Self::query_pairs(req)
+            .filter(|(name, _)| !self.is_ignored(name))
+            .collect();
+        pairs.sort_unstable();
+        pairs.iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    fn is_ignored(&self, name: &str) -> bool {
+        self.ignored_query_params.iter().any(|ignored| match ignored.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == ignored,
+        })
+    }
+
+    pub fn user_scope(req: &ApiRequest) -> Option<String> {
+        let identity = req.identity.as_ref()
+            .filter(|claims| !claims.is_anonymous())
+            .map(|claims| format!("{}\n{}", claims.iss, claims.sub));
+        let credentials = identity.or_else(|| {
+            req.headers.get(AUTHORIZATION)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        })?;
+        Some(hex::encode(Sha256::digest(credentials.as_bytes())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::jwt::Claims;
+    use crate::routing::matcher::RewriteRule;
+    use hyper::Method;
+    use regex::Regex;
+
+    fn request(uri: &str) -> ApiRequest {
+        ApiRequest::for_test(Method::GET, uri)
+    }
+
+    fn ignored() -> Vec<String> {
+        vec!["utm_*".to_string(), "gclid".to_string()]
+    }
+
+    #[test]
+    fn test_full_url_normalizes_query() {
+        let builder = CacheKeyBuilder::new(&CacheKeyStrategy::FullUrl, false, ignored()).unwrap();
+        let route = Route::default();
+        let a = builder.build(&request("http://API.example.com/users?b=2&a=1&utm_source=x"), &route, &HashMap::new());
+        let b = builder.build(&request("http://api.example.com/users?gclid=y&a=1&b=2"), &route, &HashMap::new());
+        assert_eq!(a, "GET:api.example.com/users?a=1&b=2");
+        assert_eq!(a, b);
+
+        let path_only = CacheKeyBuilder::new(&CacheKeyStrategy::PathOnly, false, ignored()).unwrap();
+        assert_eq!(path_only.build(&request("/users?a=1"), &route, &HashMap::new()), "GET:/users");
+    }
+
+    #[test]
+    fn test_custom_template() {
+        let strategy = CacheKeyStrategy::from("product_$1:$arg_lang:$http_x_tenant:$jwt_scope".to_string());
+        let builder = CacheKeyBuilder::new(&strategy, true, ignored()).unwrap();
+        let route = Route {
+            path: "/products/{slug}".into(),
+            rewrite: Some(RewriteRule { from: Regex::new("/products/(.*)").unwrap(), to: "/v2/details/$1".into() }),
+            ..Default::default()
+        };
+
+        let mut req = request("/products/shoe?lang=en&page=2");
+        req.headers.insert("x-tenant", "acme".parse().unwrap());
+        req.identity = Some(Claims { scope: "read".into(), ..Claims::anonymous() });
+        assert_eq!(builder.build(&req, &route, &HashMap::new()), "GET:/products/{slug}:product_shoe:en:acme:read");
+
+        let other = Route { path: "/products/.*".into(), ..route.clone() };
+        assert_ne!(builder.build(&req, &other, &HashMap::new()), builder.build(&req, &route, &HashMap::new()));
+
+        let err = CacheKeyBuilder::new(&CacheKeyStrategy::Custom("$cookie_id".into()), false, ignored());
+        assert!(matches!(err, Err(CacheKeyError::UnknownVariable(name)) if name == "cookie_id"));
+    }
+
+    #[test]
+    fn test_keys_are_scoped_per_user() {
+        let builder = CacheKeyBuilder::new(&CacheKeyStrategy::PathOnly, false, ignored()).unwrap();
+        let route = Route::default();
+
+        let mut alice = request("/users");
+        alice.identity = Some(Claims { sub: "alice".into(), iss: "issuer".into(), ..Claims::anonymous() });
+        let mut bob = request("/users");
+        bob.headers.insert(AUTHORIZATION, "Bearer bob".parse().unwrap());
+
+        let anonymous = builder.build(&request("/users"), &route, &HashMap::new());
+        let alice = builder.build(&alice, &route, &HashMap::new());
+        let bob = builder.build(&bob, &route, &HashMap::new());
+        assert_eq!(anonymous, "GET:/users");
+        assert!(alice.starts_with("GET:/users|user:"));
+        assert_ne!(alice, bob);
+
+        let mut req = request("/users");
+        req.headers.insert(AUTHORIZATION, "Bearer bob".parse().unwrap());
+        let shared = CacheKeyBuilder::new(&CacheKeyStrategy::PathOnly, true, ignored()).unwrap();
+        assert_eq!(shared.build(&req, &route, &HashMap::new()), "GET:/users");
+    }
+}
@@ -0,0 +1,199 @@
+use redis::{AsyncCommands, RedisResult, Script};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use crate::rate_limiting::redis_store::RedisConnection;
+
+// How often an instance checks whether another one has finished fetching
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(25);
+
+// Deletes the lock only while it still holds our token
+const RELEASE_LOCK: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+type InFlight = Arc<Mutex<HashMap<String, watch::Receiver<()>>>>;
+
+// Lets one request per cache key go upstream while identical ones wait for its
+// response to land in the cache. With Redis the same holds across instances.
+pub struct RequestCoalescer {
+    inflight: InFlight,
+    timeout: Duration,
+    redis: Option<RedisConnection>,
+    lock_ttl: Duration,
+}
+
+// Outcome of joining the requests for a key; the fetch is over once it is dropped
+#[derive(Default)]
+pub struct Flight {
+    _guard: Option<FlightGuard>,
+    waited: bool,
+}
+
+impl Flight {
+    // Another request fetched the key, or the wait timed out; worth another cache lookup
+    pub fn waited(&self) -> bool {
+        self.waited
+    }
+}
+
+struct FlightGuard {
+    inflight: InFlight,
+    key: String,
+    // Waiters are woken when this is dropped
+    _done: watch::Sender<()>,
+    lock: Option<(RedisConnection, String, String)>,
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        self.inflight.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.key);
+
+        if let Some((mut conn, lock_key, token)) = self.lock.take() {
+            tokio::spawn(async move {
+                // An unreleased lock still expires after its TTL
+                let result: RedisResult<i64> = Script::new(RELEASE_LOCK)
+                    .key(&lock_key)
+                    .arg(&token)
+                    .invoke_async(&mut conn)
+                    .await;
+                if let Err(e) = result {
+                    log::warn!("Failed to release cache fill lock {}: {}", lock_key, e);
+                }
+            });
+        }
+    }
+}
+
+impl RequestCoalescer {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            inflight: Arc::default(),
+            timeout,
+            redis: None,
+            lock_ttl: Duration::from_secs(30),
+        }
+    }
+
+    // Coalesce across instances; the lock outlives a crashed holder by at most `lock_ttl`
+    pub fn with_redis(mut self, redis: RedisConnection, lock_ttl: Duration) -> Self {
+        self.redis = Some(redis);
+        self.lock_ttl = lock_ttl;
+        self
+    }
+
+    pub async fn join(&self, key: &str) -> Flight {
+        let joined = {
+            let mut inflight = self.inflight.lock().unwrap_or_else(PoisonError::into_inner);
+            match inflight.get(key) {
+                Some(waiting) => Err(waiting.clone()),
+                None => {
+                    let (done, waiting) = watch::channel(());
+                    inflight.insert(key.to_string(), waiting);
+                    Ok(done)
+                }
+            }
+        };
+
+        let done = match joined {
+            Ok(done) => done,
+            Err(mut waiting) => {
+                // Resolves with an error once the fetching request is done
+                if tokio::time::timeout(self.timeout, waiting.changed()).await.is_err() {
+                    log::debug!("Gave up waiting for in-flight fetch of {}", key);
+                }
+                return Flight { _guard: None, waited: true };
+            }
+        };
+
+        let mut guard = FlightGuard {
+            inflight: self.inflight.clone(),
+            key: key.to_string(),
+            _done: done,
+            lock: None,
+        };
+        let waited = match self.redis.clone() {
+            Some(conn) => self.lock_or_wait(conn, &mut guard).await,
+            None => false,
+        };
+        Flight { _guard: Some(guard), waited }
+    }
+
+    // Takes the cluster-wide fill lock, or waits for whoever holds it; true if we waited
+    async fn lock_or_wait(&self, mut conn: RedisConnection, guard: &mut FlightGuard) -> bool {
+        let lock_key = format!("{}|lock", guard.key);
+        let token = uuid::Uuid::new_v4().to_string();
+        let acquired: RedisResult<Option<String>> = redis::cmd("SET")
+            .arg(&lock_key)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(self.lock_ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await;
+
+        match acquired {
+            Ok(Some(_)) => {
+                guard.lock = Some((conn, lock_key, token));
+                false
+            }
+            Ok(None) => {
+                let deadline = Instant::now() + self.timeout;
+                while Instant::now() + LOCK_POLL_INTERVAL <= deadline {
+                    tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+                    let held: RedisResult<bool> = conn.exists(&lock_key).await;
+                    if !held.unwrap_or(false) {
+                        break;
+                    }
+                }
+                true
+            }
+            // Fetch without the lock rather than fail the request
+            Err(e) => {
+                log::warn!("Cache fill lock {} unavailable: {}", lock_key, e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_waiters_are_released_with_the_leader() {
+        let coalescer = Arc::new(RequestCoalescer::new(Duration::from_secs(5)));
+        let leader = coalescer.join("GET:/products").await;
+        assert!(!leader.waited());
+
+        let follower = {
+            let coalescer = coalescer.clone();
+            tokio::spawn(async move { coalescer.join("GET:/products").await.waited() })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!follower.is_finished());
+
+        // Other keys are not held up
+        assert!(!coalescer.join("GET:/users").await.waited());
+
+        drop(leader);
+        assert!(follower.await.unwrap());
+        assert!(!coalescer.join("GET:/products").await.waited());
+    }
+
+    #[tokio::test]
+    async fn test_waiters_time_out() {
+        let coalescer = RequestCoalescer::new(Duration::from_millis(20));
+        let _leader = coalescer.join("GET:/products").await;
+
+        let started = std::time::Instant::now();
+        assert!(coalescer.join("GET:/products").await.waited());
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+}
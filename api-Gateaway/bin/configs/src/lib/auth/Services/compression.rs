@@ -0,0 +1,278 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG, VARY};
+use hyper::{Body, HeaderMap, StatusCode};
+use std::io::{Read, Write};
+use thiserror::Error;
+use crate::models::{ApiRequest, ApiResponse, config::{CompressionConfig, ContentEncoding}};
+
+#[derive(Debug, Error)]
+pub enum CompressionError {
+    #[error("Unsupported request encoding `{0}`")]
+    UnsupportedEncoding(String),
+    #[error("Invalid gzip request body: {0}")]
+    InvalidBody(#[from] std::io::Error),
+    #[error("Decompressed request body exceeds {0} bytes")]
+    TooLarge(usize),
+    #[error("Decompression task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+impl CompressionError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            CompressionError::UnsupportedEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            CompressionError::InvalidBody(_) => StatusCode::BAD_REQUEST,
+            CompressionError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            CompressionError::Task(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// The client's most preferred encoding we offer; ties go to the route's order (RFC 9110 12.5.3)
+pub fn negotiate(accept_encoding: &str, offered: &[ContentEncoding]) -> Option<ContentEncoding> {
+    let accepted: Vec<(&str, f32)> = accept_encoding.split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let coding = parts.next()?.trim();
+            let q = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!coding.is_empty()).then_some((coding, q))
+        })
+        .collect();
+    let quality = |coding: &str| {
+        accepted.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(coding))
+            .or_else(|| accepted.iter().find(|(name, _)| *name == "*"))
+            .map_or(0.0, |(_, q)| *q)
+    };
+
+    let mut best: Option<(ContentEncoding, f32)> = None;
+    for encoding in offered {
+        let q = quality(encoding.as_str());
+        if q > 0.0 && best.map_or(true, |(_, best_q)| q > best_q) {
+            best = Some((*encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+fn is_compressible_type(content_type: &str, allowed: &[String]) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    allowed.iter().any(|allowed| match allowed.strip_suffix('*') {
+        Some(prefix) => mime.starts_with(prefix),
+        None => mime == *allowed,
+    })
+}
+
+fn compress(data: &[u8], encoding: ContentEncoding, level: Option<i32>) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        ContentEncoding::Gzip => {
+            let level = level.unwrap_or(6).clamp(0, 9) as u32;
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        ContentEncoding::Brotli => {
+            // Quality 11 is far too slow for responses compressed on every request
+            let quality = level.unwrap_or(5).clamp(0, 11) as u32;
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, quality, 22);
+            encoder.write_all(data)?;
+            Ok(encoder.into_inner())
+        }
+        ContentEncoding::Zstd => zstd::bulk::compress(data, level.unwrap_or(3).clamp(1, 19)),
+    }
+}
+
+// Strong validators name the exact bytes, which compression changes
+fn weaken_etag(headers: &mut HeaderMap) {
+    let Some(etag) = headers.get(ETAG).and_then(|value| value.to_str().ok()) else { return };
+    if etag.starts_with("W/") {
+        return;
+    }
+    if let Ok(weak) = HeaderValue::from_str(&format!("W/{}", etag)) {
+        headers.insert(ETAG, weak);
+    }
+}
+
+// Compresses the response body for the client, unless it is small, of an
+// excluded type, already encoded or marked no-transform. Cached responses are
+// stored uncompressed and compressed on demand, so one entry serves every encoding.
+pub async fn compress_response(config: &CompressionConfig, accept_encoding: Option<&HeaderValue>, res: &mut ApiResponse) {
+    if res.status.is_informational() || matches!(res.status, StatusCode::NO_CONTENT | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED) {
+        return;
+    }
+    if res.headers.get(CONTENT_ENCODING).is_some_and(|value| value != "identity") {
+        return;
+    }
+    let cache_control = res.headers.get_all(CACHE_CONTROL).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+    if cache_control {
+        return;
+    }
+    let content_type = res.headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    if !is_compressible_type(content_type, &config.content_types) {
+        return;
+    }
+
+    if res.known_length().map_or(true, |length| length < config.min_bytes as u64 || length > config.max_bytes as u64) {
+        return;
+    }
+
+    // The answer depends on Accept-Encoding even for clients that get it uncompressed
+    res.headers.append(VARY, HeaderValue::from_static("Accept-Encoding"));
+    let Some(encoding) = accept_encoding
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| negotiate(value, &config.encodings))
+    else {
+        return;
+    };
+
+    let body = match hyper::body::to_bytes(std::mem::take(&mut res.body)).await {
+        Ok(body) => body,
+        Err(e) => {
+            log::warn!("Cannot read response body for compression: {}", e);
+            return;
+        }
+    };
+    let level = config.level;
+    let data = body.clone();
+    let compressed = match tokio::task::spawn_blocking(move || compress(&data, encoding, level)).await {
+        // Already compressed formats can come out larger
+        Ok(Ok(compressed)) => Some(compressed).filter(|compressed| compressed.len() < body.len()),
+        Ok(Err(e)) => {
+            log::warn!("{} compression failed: {}", encoding.as_str(), e);
+            None
+        }
+        Err(e) => {
+            log::warn!("{} compression task failed: {}", encoding.as_str(), e);
+            None
+        }
+    };
+    let Some(compressed) = compressed else {
+        res.body = Body::from(body);
+        return;
+    };
+
+    metrics::counter!(
+        "gateway_compression_saved_bytes_total",
+        (body.len() - compressed.len()) as u64,
+        "encoding" => encoding.as_str()
+    );
+    res.headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+    res.headers.insert(CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+    weaken_etag(&mut res.headers);
+    res.body = Body::from(compressed);
+}
+
+// Inflates a gzip request body in place, refusing anything larger than `max_bytes` once inflated
+pub async fn decompress_request(req: &mut ApiRequest, max_bytes: usize) -> Result<(), CompressionError> {
+    let Some(encoding) = req.headers.get(CONTENT_ENCODING) else { return Ok(()) };
+    let encoding = String::from_utf8_lossy(encoding.as_bytes()).trim().to_ascii_lowercase();
+    match encoding.as_str() {
+        "identity" => {}
+        "gzip" | "x-gzip" => {
+            let body = req.body.clone();
+            let inflated = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, CompressionError> {
+                let mut inflated = Vec::new();
+                GzDecoder::new(&body[..]).take(max_bytes as u64 + 1).read_to_end(&mut inflated)?;
+                if inflated.len() > max_bytes {
+                    return Err(CompressionError::TooLarge(max_bytes));
+                }
+                Ok(inflated)
+            })
+            .await??;
+
+            req.headers.insert(CONTENT_LENGTH, HeaderValue::from(inflated.len()));
+            req.body = Bytes::from(inflated);
+        }
+        _ => return Err(CompressionError::UnsupportedEncoding(encoding)),
+    }
+    req.headers.remove(CONTENT_ENCODING);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Method;
+
+    fn config() -> CompressionConfig {
+        CompressionConfig {
+            encodings: vec![ContentEncoding::Brotli, ContentEncoding::Zstd, ContentEncoding::Gzip],
+            min_bytes: 1024,
+            max_bytes: 1024 * 1024,
+            content_types: vec!["text/*".into(), "application/json".into()],
+            level: None,
+            decompress_requests: true,
+            max_decompressed_bytes: 4096,
+        }
+    }
+
+    fn response(content_type: &str, body: Vec<u8>) -> ApiResponse {
+        ApiResponse::new(StatusCode::OK)
+            .with_header(CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap())
+            .with_header(ETAG, HeaderValue::from_static("\"v1\""))
+            .with_body(body)
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        compress(data, ContentEncoding::Gzip, None).unwrap()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let offered = config().encodings;
+        assert_eq!(negotiate("gzip, deflate, br", &offered), Some(ContentEncoding::Brotli));
+        assert_eq!(negotiate("gzip;q=1.0, br;q=0.5", &offered), Some(ContentEncoding::Gzip));
+        assert_eq!(negotiate("*;q=0.1, br;q=0", &offered), Some(ContentEncoding::Zstd));
+        assert_eq!(negotiate("deflate, identity", &offered), None);
+        assert!(is_compressible_type("application/json; charset=utf-8", &config().content_types));
+        assert!(!is_compressible_type("image/png", &config().content_types));
+    }
+
+    #[tokio::test]
+    async fn test_compress_response() {
+        let body = b"{\"name\":\"shoe\"}".repeat(200);
+        let mut res = response("application/json", body.clone());
+        compress_response(&config(), Some(&HeaderValue::from_static("gzip")), &mut res).await;
+        assert_eq!(res.headers[CONTENT_ENCODING], "gzip");
+        assert_eq!(res.headers[VARY], "Accept-Encoding");
+        assert_eq!(res.headers[ETAG], "W/\"v1\"");
+
+        let compressed = hyper::body::to_bytes(res.body).await.unwrap();
+        let mut inflated = Vec::new();
+        GzDecoder::new(&compressed[..]).read_to_end(&mut inflated).unwrap();
+        assert_eq!(inflated, body);
+
+        // Too small, or a type that does not shrink
+        for mut res in [response("application/json", b"{}".to_vec()), response("image/png", body.clone())] {
+            compress_response(&config(), Some(&HeaderValue::from_static("br")), &mut res).await;
+            assert!(!res.headers.contains_key(CONTENT_ENCODING));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decompress_request() {
+        let mut req = ApiRequest::for_test(Method::POST, "/upload");
+        req.body = Bytes::from(gzip(b"hello"));
+        req.headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        decompress_request(&mut req, 4096).await.unwrap();
+        assert_eq!(&req.body[..], b"hello");
+        assert!(!req.headers.contains_key(CONTENT_ENCODING));
+
+        // A small upload inflating past the limit
+        req.body = Bytes::from(gzip(&[0; 8192]));
+        req.headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        let err = decompress_request(&mut req, 4096).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        req.headers.insert(CONTENT_ENCODING, HeaderValue::from_static("br"));
+        assert!(matches!(decompress_request(&mut req, 4096).await, Err(CompressionError::UnsupportedEncoding(_))));
+    }
+}
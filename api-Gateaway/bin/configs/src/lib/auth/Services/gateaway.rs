@@ -0,0 +1,1038 @@
+use hyper::{Body, HeaderMap, Method, Request, Response, Server, StatusCode};
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, SET_COOKIE, WWW_AUTHENTICATE};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Instant};
+use crate::auth::{
+    api_key::ApiKeyAuthenticator,
+    basic::BasicAuthenticator,
+    jwt::{Authenticator, Claims, JwtValidator},
+    oauth::{IntrospectionResponse, OAuthIntrospector},
+    oidc::{OidcLoginHandler, OidcOutcome},
+    signature::{HmacSignatureVerifier, SignatureVerifier},
+};
+use crate::models::{
+    ApiRequest, ApiResponse,
+    request::client_addr,
+    config::{AuthMethod, AuthMode, ChainPolicy, ConcurrencyScope, RequestCost},
+};
+use crate::rate_limiting::{
+    adaptive::AdaptiveLimiter,
+    concurrency::ConcurrencyLimiter,
+    headers::RateLimitStatus,
+    hybrid::HybridRateLimiter,
+    overrides::Cidr,
+    policy::{CompiledPolicy, CompiledRule, PolicyMode, RateLimitPolicies},
+    quota::{QuotaManager, QuotaStatus},
+};
+use crate::routing::{matcher::{Route, RouteMatcher}, proxy::ProxyHandler};
+use crate::services::{
+    admin::AdminApi,
+    cache::{CacheScope, CacheService, CacheStatus},
+    cache_key::CacheKeyBuilder,
+    compression::{self, CompressionError},
+    http_cache::{self, CachedResponse},
+    idempotency::{Claim, IdempotencyError, IdempotencyStore},
+    request_limits,
+};
+use crate::utils::error::ApiError;
+use tokio::sync::Mutex;
+
+pub struct GatewayService {
+    router: Arc<RouteMatcher>,
+    proxy: Arc<ProxyHandler>,
+    rate_limiter: Arc<HybridRateLimiter>,
+    rate_limit_policies: Arc<RateLimitPolicies>,
+    jwt_validator: Arc<JwtValidator>,
+    oauth_introspector: Arc<OAuthIntrospector>,
+    signature_verifiers: HashMap<String, Arc<dyn SignatureVerifier>>,
+    oidc_handlers: HashMap<String, Arc<OidcLoginHandler>>,
+    oidc_callbacks: HashMap<String, Arc<OidcLoginHandler>>,
+    basic_authenticators: HashMap<String, Arc<BasicAuthenticator>>,
+    api_key_authenticators: HashMap<String, Arc<ApiKeyAuthenticator>>,
+    concurrency_limiters: HashMap<String, Arc<ConcurrencyLimiter>>,
+    // Keyed by backend
+    adaptive_limiters: HashMap<String, Arc<AdaptiveLimiter>>,
+    quotas: Option<Arc<QuotaManager>>,
+    admin: Option<Arc<AdminApi>>,
+    cache: Option<Arc<CacheService>>,
+    // Keyed by route path
+    cache_keys: HashMap<String, Arc<CacheKeyBuilder>>,
+    idempotency: Option<Arc<IdempotencyStore>>,
+    // Peers whose X-Forwarded-For is believed
+    trusted_proxies: Vec<Cidr>,
+    default_max_body_bytes: usize,
+}
+
+impl GatewayService {
+    pub fn new(
+        router: RouteMatcher,
+        rate_limiter: HybridRateLimiter,
+        jwt_validator: JwtValidator,
+        oauth_introspector: OAuthIntrospector,
+    ) -> Result<Self, ApiError> {
+        let mut signature_verifiers: HashMap<String, Arc<dyn SignatureVerifier>> = HashMap::new();
+        for route in router.routes() {
+            if let Some(config) = &route.signature_verification {
+                match HmacSignatureVerifier::from_config(config) {
+                    Ok(verifier) => {
+                        signature_verifiers.insert(route.path.clone(), Arc::new(verifier));
+                    }
+                    // Without a verifier every request to the route would be rejected
+                    Err(e) => return Err(ApiError::ConfigError(format!("Signature verifier for {} not configured: {}", route.path, e))),
+                }
+            }
+        }
+
+        let mut oidc_handlers = HashMap::new();
+        let mut oidc_callbacks = HashMap::new();
+        for route in router.routes() {
+            if let Some(config) = &route.authentication.oidc_login {
+                match OidcLoginHandler::new(config.clone()) {
+                    Ok(handler) => {
+                        let handler = Arc::new(handler);
+                        // Routes sharing an IdP client also share its callback
+                        oidc_callbacks.insert(handler.callback_path().to_string(), handler.clone());
+                        oidc_handlers.insert(route.path.clone(), handler);
+                    }
+                    // Serving the route without its login would leave it open
+                    Err(e) => return Err(ApiError::ConfigError(format!("OIDC login for {} not configured: {}", route.path, e))),
+                }
+            }
+        }
+
+        let mut basic_authenticators = HashMap::new();
+        for route in router.routes() {
+            if let Some(config) = &route.authentication.basic {
+                match BasicAuthenticator::new(config) {
+                    Ok(authenticator) => {
+                        basic_authenticators.insert(route.path.clone(), Arc::new(authenticator));
+                    }
+                    Err(e) => return Err(ApiError::ConfigError(format!("Basic auth for {} not configured: {}", route.path, e))),
+                }
+            }
+        }
+
+        let api_key_authenticators = router.routes().iter()
+            .filter_map(|route| {
+                let config = route.authentication.api_key.as_ref()?;
+                Some((route.path.clone(), Arc::new(ApiKeyAuthenticator::new(config))))
+            })
+            .collect();
+
+        let mut concurrency_limiters = HashMap::new();
+        let mut upstream_limiters: HashMap<String, Arc<ConcurrencyLimiter>> = HashMap::new();
+        for route in router.routes() {
+            let Some(config) = &route.concurrency else { continue };
+            let limiter = match config.scope {
+                ConcurrencyScope::Route => Arc::new(ConcurrencyLimiter::new(
+                    format!("route:{}", route.path), config, rate_limiter.redis().cloned(),
+                )),
+                // The first route to an upstream defines its limit
+                ConcurrencyScope::Upstream => upstream_limiters
+                    .entry(route.backend.clone())
+                    .or_insert_with(|| Arc::new(ConcurrencyLimiter::new(
+                        format!("upstream:{}", route.backend), config, rate_limiter.redis().cloned(),
+                    )))
+                    .clone(),
+            };
+            concurrency_limiters.insert(route.path.clone(), limiter);
+        }
+
+        let mut adaptive_limiters = HashMap::new();
+        for route in router.routes() {
+            if let Some(config) = &route.adaptive_concurrency {
+                adaptive_limiters
+                    .entry(route.backend.clone())
+                    .or_insert_with(|| Arc::new(AdaptiveLimiter::new(route.backend.clone(), config)));
+            }
+        }
+
+        Ok(Self {
+            router: Arc::new(router),
+            proxy: Arc::new(ProxyHandler::new()),
+            rate_limiter: Arc::new(rate_limiter),
+            rate_limit_policies: Arc::new(RateLimitPolicies::default()),
+            jwt_validator: Arc::new(jwt_validator),
+            oauth_introspector: Arc::new(oauth_introspector),
+            signature_verifiers,
+            oidc_handlers,
+            oidc_callbacks,
+            basic_authenticators,
+            api_key_authenticators,
+            concurrency_limiters,
+            adaptive_limiters,
+            quotas: None,
+            admin: None,
+            cache: None,
+            cache_keys: HashMap::new(),
+            idempotency: None,
+            trusted_proxies: Vec::new(),
+            default_max_body_bytes: request_limits::DEFAULT_MAX_BODY_BYTES,
+        })
+    }
+
+    pub fn with_rate_limit_policies(mut self, policies: RateLimitPolicies) -> Result<Self, ApiError> {
+        for route in self.router.routes() {
+            if let Some(name) = route.rate_limit.as_ref().filter(|name| !policies.contains(name)) {
+                return Err(ApiError::ConfigError(format!("Route {} uses unknown rate limit policy {}", route.path, name)));
+            }
+        }
+        self.rate_limit_policies = Arc::new(policies);
+        Ok(self)
+    }
+
+    pub fn with_quotas(mut self, quotas: Arc<QuotaManager>) -> Self {
+        self.quotas = Some(quotas);
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Arc<CacheService>) -> Result<Self, ApiError> {
+        for route in self.router.routes() {
+            let Some(config) = &route.cache else { continue };
+            match cache.key_builder(config) {
+                Ok(builder) => {
+                    self.cache_keys.insert(route.path.clone(), Arc::new(builder));
+                }
+                Err(e) => return Err(ApiError::ConfigError(format!("Cache key for route {} not configured: {}", route.path, e))),
+            }
+        }
+        self.cache = Some(cache);
+        Ok(self)
+    }
+
+    pub fn with_idempotency(mut self, store: Arc<IdempotencyStore>) -> Self {
+        self.idempotency = Some(store);
+        self
+    }
+
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<Cidr>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    pub fn with_default_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.default_max_body_bytes = max_body_bytes;
+        self
+    }
+
+    pub fn with_admin(mut self, admin: AdminApi) -> Self {
+        self.admin = Some(Arc::new(admin));
+        self
+    }
+
+    pub fn rate_limit_policies(&self) -> Arc<RateLimitPolicies> {
+        self.rate_limit_policies.clone()
+    }
+
+    pub fn register_signature_verifier(&mut self, path: impl Into<String>, verifier: Arc<dyn SignatureVerifier>) {
+        self.signature_verifiers.insert(path.into(), verifier);
+    }
+
+    // `remote_addr` is the socket peer, which may be a trusted proxy
+    pub async fn handle_request(&self, req: Request<Body>, remote_addr: SocketAddr) -> ApiResponse {
+        let start_time = Instant::now();
+        // Routed before the body is read, so the route's size limits apply while it streams in
+        let matched = self.router.find_route(req.uri().path(), req.method().as_str());
+        let mut api_request = match self.build_api_request(req, remote_addr, matched.as_ref().ok().map(|(route, _)| route)).await {
+            Ok(r) => r,
+            Err(e) => return self.handle_error(e, start_time),
+        };
+
+        // Admin endpoints
+        if let Some(admin) = self.admin.as_ref().filter(|admin| admin.matches(api_request.uri.path())) {
+            return self.finalize_response(admin.handle(&api_request).await, start_time);
+        }
+
+        // OIDC callback
+        if let Some(handler) = self.oidc_callbacks.get(api_request.uri.path()) {
+            return match handler.handle_callback(&api_request).await {
+                Ok(res) => self.finalize_response(res, start_time),
+                Err(e) => {
+                    log::warn!("OIDC callback failed: {}", e);
+                    self.handle_error(GatewayError::Unauthorized, start_time)
+                }
+            };
+        }
+
+        // Routing
+        let (route, params) = match matched {
+            Ok(r) => r,
+            Err(e) => return self.handle_error(e.into(), start_time),
+        };
+
+        // Responses are compressed here, so backends answer uncompressed and one
+        // cache entry serves every encoding
+        let accept_encoding = route.compression.as_ref()
+            .and_then(|_| api_request.headers.remove(ACCEPT_ENCODING))
+            .filter(|_| api_request.method != Method::HEAD);
+
+        // Browser login (BFF mode)
+        let mut session_cookie = None;
+        if let Some(handler) = self.oidc_handlers.get(&route.path) {
+            match handler.authenticate(&api_request).await {
+                Ok(OidcOutcome::Authenticated { access_token, set_cookie }) => {
+                    match HeaderValue::from_str(&format!("Bearer {}", access_token)) {
+                        Ok(value) => api_request.headers.insert(AUTHORIZATION, value),
+                        Err(_) => return self.handle_error(GatewayError::Unauthorized, start_time),
+                    };
+                    session_cookie = set_cookie;
+                }
+                Ok(OidcOutcome::Redirect(res)) => return self.finalize_response(res, start_time),
+                Err(e) => {
+                    log::debug!("OIDC login required for {}: {}", route.path, e);
+                    return self.handle_error(GatewayError::Unauthorized, start_time);
+                }
+            }
+        }
+
+        // Authentication
+        if let Err(e) = self.authenticate(&route, &mut api_request).await {
+            return self.handle_error(e, start_time);
+        }
+
+        // Rate Limiting; overrides may swap the policy or skip limiting altogether
+        let policy = self.rate_limit_policies.resolve(route.rate_limit.as_deref(), &api_request);
+        let rules = policy
+            .map(|policy| self.rate_limit_policies.evaluate(policy, &api_request))
+            .unwrap_or_default();
+        let cost = Self::request_cost(&route, &api_request);
+        // Once admitted, every response carries the rate limit headers, errors included
+        let rate_limit = match self.check_rate_limits(&route, policy, &rules, cost).await {
+            Ok(status) => status,
+            Err(e) => return self.handle_error(e, start_time),
+        };
+
+        // Signature verification
+        if let Err(e) = self.verify_signature(&route, &api_request) {
+            return self.with_client_headers(self.handle_error(e, start_time), session_cookie, rate_limit, None);
+        }
+
+        // Compressed uploads, inflated for backends that cannot read them
+        if let Some(config) = route.compression.as_ref().filter(|config| config.decompress_requests) {
+            if let Err(e) = compression::decompress_request(&mut api_request, config.max_decompressed_bytes).await {
+                log::debug!("Rejected compressed request to {}: {}", route.path, e);
+                return self.with_client_headers(self.handle_error(GatewayError::Compression(e), start_time), session_cookie, rate_limit, None);
+            }
+        }
+
+        // Idempotency keys; retries get the stored response, duplicates still in flight are turned away
+        let mut idempotency_claim = None;
+        if let (Some(store), Some(config)) = (&self.idempotency, &route.idempotency) {
+            match store.begin(&route, config, &api_request).await {
+                Ok(Some(Claim::Acquired(claim))) => idempotency_claim = Some(claim),
+                Ok(Some(Claim::Replay(res))) => {
+                    let mut response = self.finalize_response(res, start_time);
+                    Self::compress(&route, accept_encoding.as_ref(), &mut response).await;
+                    return self.with_client_headers(response, session_cookie, rate_limit, None);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    if let IdempotencyError::Store(_) | IdempotencyError::Encoding(_) = &e {
+                        log::error!("Idempotency check for {} failed: {}", route.path, e);
+                    }
+                    return self.with_client_headers(self.handle_error(GatewayError::Idempotency(e), start_time), session_cookie, rate_limit, None);
+                }
+            }
+        }
+
+        // Response cache; stale entries are revalidated with the backend, in the
+        // background while they are within their stale-while-revalidate window
+        let cache = self.cache.as_deref()
+            .zip(route.cache.as_ref())
+            .filter(|_| http_cache::is_cacheable_request(&api_request));
+        let mut cache_lookup = None;
+        let mut cache_status = route.cache.is_some().then_some(CacheStatus::Bypass);
+        // Held until the response is stored, so coalesced requests find it in the cache
+        let mut _cache_fill = None;
+        if let (Some((cache, config)), Some(keys)) = (cache, self.cache_keys.get(&route.path)) {
+            let key = keys.build(&api_request, &route, &params);
+            let request_headers = api_request.headers.clone();
+            let scope = CacheScope { route: &route.path, tier: config.tier };
+            let mut stored = cache.lookup(&key, &request_headers, scope).await;
+            cache_status = Some(CacheStatus::Miss);
+
+            // Identical misses wait for one upstream fetch instead of all reaching the backend
+            let now = http_cache::now();
+            let usable = stored.as_ref().is_some_and(|entry| entry.is_fresh(now) || entry.is_stale_while_revalidate(now));
+            if !usable && !http_cache::requires_revalidation(&request_headers) {
+                let flight = cache.coalesce(&key).await;
+                if flight.waited() {
+                    stored = cache.lookup(&key, &request_headers, scope).await;
+                }
+                _cache_fill = Some(flight);
+            }
+
+            if let Some(entry) = &stored {
+                let now = http_cache::now();
+                if !http_cache::requires_revalidation(&request_headers) {
+                    if entry.is_fresh(now) {
+                        let mut response = self.finalize_response(entry.respond(&request_headers, now), start_time);
+                        Self::apply_cache_status(&route, &mut response, Some(CacheStatus::Hit));
+                        Self::compress(&route, accept_encoding.as_ref(), &mut response).await;
+                        return self.with_client_headers(response, session_cookie, rate_limit, None);
+                    }
+                    if entry.is_stale_while_revalidate(now) {
+                        let mut refresh = api_request.clone();
+                        entry.add_validators(&mut refresh.headers);
+                        self.spawn_refresh(&route, &params, refresh, &key, &request_headers, entry.clone());
+                        let mut response = self.finalize_response(entry.respond_stale(&request_headers, now, false), start_time);
+                        Self::apply_cache_status(&route, &mut response, Some(CacheStatus::Stale));
+                        Self::compress(&route, accept_encoding.as_ref(), &mut response).await;
+                        return self.with_client_headers(response, session_cookie, rate_limit, None);
+                    }
+                }
+                entry.add_validators(&mut api_request.headers);
+            }
+            cache_lookup = Some((key, api_request.uri.path().to_string(), request_headers, stored));
+        }
+
+        // Concurrency limits, held until the backend has answered
+        let _permit = match self.concurrency_limiters.get(&route.path) {
+            Some(limiter) => match limiter.acquire().await {
+                Ok(permit) => Some(permit),
+                Err(e) => {
+                    log::warn!("Rejected request to {}: {}", route.path, e);
+                    return self.with_client_headers(self.handle_error(GatewayError::ConcurrencyLimitExceeded, start_time), session_cookie, rate_limit, None);
+                }
+            },
+            None => None,
+        };
+
+        let adaptive = match self.adaptive_limiters.get(&route.backend) {
+            Some(limiter) => match limiter.try_acquire() {
+                Some(token) => Some(token),
+                None => {
+                    log::warn!("Shedding request to {}: adaptive limit {} reached", route.backend, limiter.limit());
+                    return self.with_client_headers(self.handle_error(GatewayError::ConcurrencyLimitExceeded, start_time), session_cookie, rate_limit, None);
+                }
+            },
+            None => None,
+        };
+
+        // Quotas, charged only once the request has been admitted to the backend,
+        // so requests shed above do not use up the consumer's allowance
+        let quota = match self.check_quota(&api_request).await {
+            Ok(status) => status,
+            Err(e) => return self.with_client_headers(self.handle_error(e, start_time), session_cookie, rate_limit, None),
+        };
+
+        // Proxying
+        let upstream_start = Instant::now();
+        let mut result = self.proxy.forward_request(&route, api_request.uri.path(), params, api_request).await;
+
+        if let Some(token) = adaptive {
+            match &result {
+                Ok(res) if !Self::is_overload(res.status) => token.success(upstream_start.elapsed()),
+                _ => token.dropped(),
+            }
+        }
+
+        // Backend-reported cost beyond what was charged on admission
+        if let (Ok(res), Some(RequestCost::ResponseHeader { header, upfront, max })) = (&mut result, &route.rate_limit_cost) {
+            let reported = Self::reported_cost(res, header, *max);
+            if let (Some(reported), Some(policy)) = (reported, policy) {
+                self.rate_limiter.debit(policy, &rules, reported.saturating_sub(*upfront)).await;
+            }
+        }
+
+        // Failed attempts free the key so the client can retry
+        if let (Some(claim), Ok(res)) = (idempotency_claim, &mut result) {
+            if !res.status.is_server_error() {
+                claim.complete(res).await;
+            }
+        }
+
+        if let (Some((cache, _)), Some((key, path, request_headers, stored))) = (cache, cache_lookup) {
+            result = match result {
+                Ok(res) if !res.status.is_server_error() => {
+                    Self::update_cache(cache, &route, &key, &path, &request_headers, stored, res).await
+                        .map(|(res, status)| {
+                            cache_status = Some(status);
+                            res
+                        })
+                }
+                // Backend errors and an open circuit breaker fall back to a recent enough copy
+                failed => {
+                    let now = http_cache::now();
+                    match stored.filter(|entry| entry.is_usable_on_error(now)) {
+                        Some(entry) => {
+                            log::warn!("Serving stale {} after a backend failure", key);
+                            cache_status = Some(CacheStatus::Stale);
+                            Ok(entry.respond_stale(&request_headers, now, true))
+                        }
+                        None => failed,
+                    }
+                }
+            };
+        }
+
+        let mut response = match result {
+            Ok(res) => self.finalize_response(res, start_time),
+            Err(e) => self.handle_error(e, start_time),
+        };
+        Self::apply_cache_status(&route, &mut response, cache_status);
+        Self::compress(&route, accept_encoding.as_ref(), &mut response).await;
+        self.with_client_headers(response, session_cookie, rate_limit, quota)
+    }
+
+    // Cache hits are compressed on demand like fresh responses
+    async fn compress(route: &Route, accept_encoding: Option<&HeaderValue>, response: &mut ApiResponse) {
+        if let Some(config) = &route.compression {
+            compression::compress_response(config, accept_encoding, response).await;
+        }
+    }
+
+    // X-Cache is opt-in per route
+    fn apply_cache_status(route: &Route, response: &mut ApiResponse, status: Option<CacheStatus>) {
+        let enabled = route.cache.as_ref().is_some_and(|config| config.x_cache_header);
+        if let Some(status) = status.filter(|_| enabled) {
+            status.apply(&mut response.headers);
+        }
+    }
+
+    fn with_client_headers(
+        &self,
+        mut response: ApiResponse,
+        session_cookie: Option<HeaderValue>,
+        rate_limit: Option<RateLimitStatus>,
+        quota: Option<QuotaStatus>,
+    ) -> ApiResponse {
+        if let Some(cookie) = session_cookie {
+            response.headers.append(SET_COOKIE, cookie);
+        }
+        if let Some(rate_limit) = rate_limit {
+            rate_limit.apply(&mut response.headers, self.rate_limit_policies.header_options());
+        }
+        if let Some(quota) = quota {
+            quota.apply(&mut response.headers);
+        }
+        response
+    }
+
+    // Revalidates a stale entry off the request path, once per key at a time
+    fn spawn_refresh(
+        &self,
+        route: &Route,
+        params: &HashMap<String, String>,
+        req: ApiRequest,
+        key: &str,
+        request_headers: &HeaderMap,
+        stored: Arc<CachedResponse>,
+    ) {
+        let Some(cache) = self.cache.clone() else { return };
+        let Some(guard) = cache.begin_refresh(key) else { return };
+        let proxy = self.proxy.clone();
+        let (route, params, key, request_headers) = (route.clone(), params.clone(), key.to_string(), request_headers.clone());
+
+        tokio::spawn(async move {
+            let _guard = guard;
+            let path = req.uri.path().to_string();
+            match proxy.forward_request(&route, &path, params, req).await {
+                Ok(res) if !res.status.is_server_error() => {
+                    if let Err(e) = Self::update_cache(&cache, &route, &key, &path, &request_headers, Some(stored), res).await {
+                        log::warn!("Background refresh of {} failed: {:?}", key, e);
+                    }
+                }
+                Ok(res) => log::warn!("Background refresh of {} failed with {}", key, res.status),
+                Err(e) => log::warn!("Background refresh of {} failed: {:?}", key, e),
+            }
+        });
+    }
+
+    // Stores cacheable upstream answers; a 304 for a stale entry is answered from the cache
+    async fn update_cache(
+        cache: &CacheService,
+        route: &Route,
+        key: &str,
+        path: &str,
+        request_headers: &HeaderMap,
+        stored: Option<Arc<CachedResponse>>,
+        mut res: ApiResponse,
+    ) -> Result<(ApiResponse, CacheStatus), GatewayError> {
+        let config = route.cache.clone().unwrap_or_default();
+        let scope = CacheScope { route: &route.path, tier: config.tier };
+        if let Some(stored) = stored.filter(|_| res.status == StatusCode::NOT_MODIFIED) {
+            let entry = stored.revalidated(&res.headers, config.ttl_seconds)
+                .with_stale_defaults(config.stale_while_revalidate_seconds, config.stale_if_error_seconds);
+            let response = entry.respond(request_headers, http_cache::now());
+            cache.store(key, path, request_headers, entry, scope).await;
+            return Ok((response, CacheStatus::Hit));
+        }
+
+        match cache.capture(request_headers, &mut res, config.ttl_seconds).await {
+            Ok(Some(entry)) => {
+                let entry = entry.with_stale_defaults(config.stale_while_revalidate_seconds, config.stale_if_error_seconds);
+                cache.store(key, path, request_headers, entry, scope).await;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log::warn!("Failed to read upstream body for {}: {}", key, e);
+                return Err(GatewayError::BackendError);
+            }
+        }
+        Ok((res, CacheStatus::Miss))
+    }
+
+    async fn build_api_request(&self, req: Request<Body>, peer: SocketAddr, route: Option<&Route>) -> Result<ApiRequest, GatewayError> {
+        // Unrouted requests are answered from their head alone, except for the
+        // gateway's own admin and OIDC callback endpoints
+        let path = req.uri().path();
+        let needs_body = route.is_some()
+            || self.admin.as_ref().is_some_and(|admin| admin.matches(path))
+            || self.oidc_callbacks.contains_key(path);
+        let (parts, body) = if needs_body {
+            // Raw bytes are kept so signatures can be checked over the exact payload
+            request_limits::read_request(req, route, self.default_max_body_bytes).await.map_err(GatewayError::Rejected)?
+        } else {
+            (req.into_parts().0, Bytes::new())
+        };
+
+        Ok(ApiRequest {
+            remote_addr: Some(client_addr(peer, &parts.headers, &self.trusted_proxies)),
+            method: parts.method,
+            uri: parts.uri,
+            headers: parts.headers,
+            body,
+            received_at: Instant::now(),
+            path_params: HashMap::new(),
+            query_params: HashMap::new(),
+            identity: None,
+        })
+    }
+
+    fn verify_signature(&self, route: &Route, req: &ApiRequest) -> Result<(), GatewayError> {
+        if route.signature_verification.is_none() {
+            return Ok(());
+        }
+
+        // Fail closed when the route expects a signature but has no verifier
+        let verifier = self.signature_verifiers.get(&route.path)
+            .ok_or(GatewayError::InvalidSignature)?;
+
+        verifier.verify(&req.headers, &req.body).map_err(|e| {
+            log::warn!("Rejected request to {}: {}", route.path, e);
+            GatewayError::InvalidSignature
+        })
+    }
+
+    async fn authenticate(&self, route: &Route, req: &mut ApiRequest) -> Result<(), GatewayError> {
+        let auth = &route.authentication;
+        let mode = auth.effective_mode();
+        if mode == AuthMode::Disabled {
+            return Ok(());
+        }
+
+        // Routes without authenticators of their own use the global JWT validator
+        let mut methods = auth.methods();
+        if methods.is_empty() {
+            methods.push(AuthMethod::Jwt);
+        }
+        let mut identity = None;
+        let mut rejected = None;
+        let mut missing = false;
+
+        for method in methods.iter().filter(|m| **m != AuthMethod::Anonymous) {
+            match self.try_authenticate(method, route, req).await {
+                Ok(Some(claims)) => {
+                    identity.get_or_insert(claims);
+                    if auth.chain_policy == ChainPolicy::FirstSuccess {
+                        break;
+                    }
+                }
+                Ok(None) => missing = true,
+                // First-success keeps going so JWT and OAuth can share the Bearer header
+                Err(e) if auth.chain_policy == ChainPolicy::FirstSuccess => rejected = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        if auth.chain_policy == ChainPolicy::AllMustPass && missing && identity.is_some() {
+            return Err(self.unauthorized(route));
+        }
+
+        if let Some(claims) = identity {
+            req.identity = Some(claims);
+            return Ok(());
+        }
+
+        // Credentials that were presented but not accepted are never downgraded to anonymous
+        if let Some(e) = rejected {
+            return Err(e);
+        }
+
+        if mode == AuthMode::Optional || methods.contains(&AuthMethod::Anonymous) {
+            req.identity = Some(Claims::anonymous());
+            return Ok(());
+        }
+
+        Err(self.unauthorized(route))
+    }
+
+    // Ok(None) means the request carried no credentials for this method
+    async fn try_authenticate(
+        &self,
+        method: &AuthMethod,
+        route: &Route,
+        req: &ApiRequest,
+    ) -> Result<Option<Claims>, GatewayError> {
+        let authorization = req.headers.get(AUTHORIZATION).and_then(|h| h.to_str().ok());
+        let bearer = authorization.and_then(|h| h.strip_prefix("Bearer "));
+
+        let claims = match method {
+            AuthMethod::Jwt => {
+                let Some(token) = bearer else { return Ok(None) };
+                self.jwt_validator.validate_token(token).await
+                    .map_err(|_| GatewayError::Unauthorized)?
+            }
+            AuthMethod::Oauth => {
+                let Some(token) = bearer else { return Ok(None) };
+                self.oauth_introspector.introspect_token(token).await
+                    .map(IntrospectionResponse::into_claims)
+                    .map_err(|_| GatewayError::Unauthorized)?
+            }
+            AuthMethod::Basic => {
+                let Some(basic) = self.basic_authenticators.get(&route.path) else { return Ok(None) };
+                let Some(credentials) = authorization.and_then(|h| h.strip_prefix("Basic ")) else { return Ok(None) };
+                basic.validate_token(credentials).await
+                    .map_err(|_| GatewayError::AuthChallenge(basic.challenge()))?
+            }
+            AuthMethod::ApiKey => {
+                let Some(api_key) = self.api_key_authenticators.get(&route.path) else { return Ok(None) };
+                let Some(key) = api_key.extract(&req.headers) else { return Ok(None) };
+                api_key.validate_token(key).await
+                    .map_err(|_| GatewayError::Unauthorized)?
+            }
+            AuthMethod::Anonymous => return Ok(None),
+        };
+
+        Ok(Some(claims))
+    }
+
+    fn unauthorized(&self, route: &Route) -> GatewayError {
+        match self.basic_authenticators.get(&route.path) {
+            Some(basic) => GatewayError::AuthChallenge(basic.challenge()),
+            None => GatewayError::Unauthorized,
+        }
+    }
+
+    // Shadow policies and bypassed requests never block and send no rate limit headers
+    async fn check_rate_limits(
+        &self,
+        route: &Route,
+        policy: Option<&CompiledPolicy>,
+        rules: &[(String, &CompiledRule)],
+        cost: u32,
+    ) -> Result<Option<RateLimitStatus>, GatewayError> {
+        let Some(policy) = policy else { return Ok(None) };
+        let shadow = policy.mode == PolicyMode::Shadow;
+
+        let (allowed, remaining, reset) = match self.rate_limiter.check(policy, rules, cost).await {
+            Ok(result) => result,
+            Err(e) if shadow => {
+                log::warn!("Rate limit store unavailable for shadow policy {}: {}", policy.name, e);
+                return Ok(None);
+            }
+            Err(e) => {
+                log::error!("Rate limit store unavailable, rejecting request to {}: {}", route.path, e);
+                return Err(GatewayError::RateLimiterUnavailable);
+            }
+        };
+
+        if shadow {
+            if !allowed {
+                let key = rules.first().map(|(key, _)| key.as_str()).unwrap_or_default();
+                log::info!("Shadow policy {} would have rejected request to {} ({})", policy.name, route.path, key);
+                metrics::increment_counter!("gateway_rate_limit_shadow_rejections_total", "policy" => policy.name.clone());
+            }
+            return Ok(None);
+        }
+
+        // Composite policies advertise their strictest limit
+        let limit = rules.iter()
+            .map(|(_, rule)| rule.config.capacity)
+            .min()
+            .unwrap_or_default();
+
+        let status = RateLimitStatus { allowed, limit, remaining, reset };
+        if !allowed {
+            return Err(GatewayError::RateLimitExceeded(status));
+        }
+        Ok(Some(status))
+    }
+
+    // Units of the rate limit consumed on admission
+    fn request_cost(route: &Route, req: &ApiRequest) -> u32 {
+        match &route.rate_limit_cost {
+            None => 1,
+            Some(RequestCost::Fixed(cost)) => *cost,
+            Some(RequestCost::BodySize { bytes_per_unit, min, max }) => {
+                let units = (req.body.len() as u64).div_ceil((*bytes_per_unit).max(1));
+                let units = u32::try_from(units).unwrap_or(u32::MAX).max(*min);
+                max.map_or(units, |max| units.min(max))
+            }
+            Some(RequestCost::ResponseHeader { upfront, .. }) => *upfront,
+        }
+    }
+
+    // The cost header is meant for the gateway and is not passed on to clients
+    fn reported_cost(response: &mut ApiResponse, header: &str, max: u32) -> Option<u32> {
+        let value = response.headers.remove(header)?;
+        let cost = value.to_str().ok()?.trim().parse::<u32>().ok()?;
+        Some(cost.min(max))
+    }
+
+    // Only authenticated consumers are metered; a store outage rejects the request unless the quotas fail open
+    async fn check_quota(&self, req: &ApiRequest) -> Result<Option<QuotaStatus>, GatewayError> {
+        let Some(quotas) = &self.quotas else { return Ok(None) };
+        let Some(identity) = req.identity.as_ref().filter(|claims| !claims.is_anonymous()) else {
+            return Ok(None);
+        };
+
+        match quotas.check(&identity.sub).await {
+            Ok(Some(status)) if !status.allowed => Err(GatewayError::QuotaExceeded(status)),
+            Ok(status) => Ok(status),
+            Err(e) if quotas.fails_open() => {
+                log::warn!("Quota store unavailable, not metering {}: {}", identity.sub, e);
+                metrics::increment_counter!("gateway_quota_unmetered_total");
+                Ok(None)
+            }
+            Err(e) => {
+                log::error!("Quota store unavailable, rejecting request from {}: {}", identity.sub, e);
+                Err(GatewayError::RateLimiterUnavailable)
+            }
+        }
+    }
+
+    // Upstream answers that mean it is past its capacity
+    fn is_overload(status: StatusCode) -> bool {
+        status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn finalize_response(&self, mut response: ApiResponse, start_time: Instant) -> ApiResponse {
+        response.latency = start_time.elapsed();
+        response.headers.insert("X-Served-By", "api-gateway".parse().unwrap());
+        response
+    }
+
+    fn handle_error(&self, error: GatewayError, start_time: Instant) -> ApiResponse {
+        if let GatewayError::AuthChallenge(challenge) = &error {
+            return ApiResponse::new(StatusCode::UNAUTHORIZED)
+                .with_header(WWW_AUTHENTICATE, challenge.clone())
+                .with_latency(start_time.elapsed());
+        }
+
+        let rate_limit = match &error {
+            GatewayError::RateLimitExceeded(status) => Some(*status),
+            _ => None,
+        };
+        let quota = match &error {
+            GatewayError::QuotaExceeded(status) => Some(*status),
+            _ => None,
+        };
+
+        let status = match error {
+            GatewayError::Unauthorized => StatusCode::UNAUTHORIZED,
+            GatewayError::InvalidSignature => StatusCode::UNAUTHORIZED,
+            GatewayError::BadRequest => StatusCode::BAD_REQUEST,
+            GatewayError::RateLimitExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            GatewayError::QuotaExceeded(status) => status.on_exceeded.status(),
+            GatewayError::RateLimiterUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            GatewayError::ConcurrencyLimitExceeded => StatusCode::SERVICE_UNAVAILABLE,
+            GatewayError::Idempotency(ref e) => e.status(),
+            GatewayError::Compression(ref e) => e.status(),
+            GatewayError::Rejected(ref e) => e.status_code(),
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        
+        let mut response = ApiResponse::new(status)
+            .json(&ErrorResponse::from(error))
+            .with_latency(start_time.elapsed());
+
+        if let Some(rate_limit) = rate_limit {
+            rate_limit.apply(&mut response.headers, self.rate_limit_policies.header_options());
+        }
+        if let Some(quota) = quota {
+            quota.apply(&mut response.headers);
+        }
+        response
+    }
+}
+
+#[derive(Debug)]
+pub enum GatewayError {
+    Unauthorized,
+    // 401 carrying a `WWW-Authenticate` challenge
+    AuthChallenge(HeaderValue),
+    InvalidSignature,
+    BadRequest,
+    RateLimitExceeded(RateLimitStatus),
+    // 429 or 402 depending on the consumer's plan
+    QuotaExceeded(QuotaStatus),
+    // Redis unreachable on a policy that fails closed
+    RateLimiterUnavailable,
+    // Too many requests in flight to the route or its upstream
+    ConcurrencyLimitExceeded,
+    // Missing, reused or in-flight idempotency key, or its store unreachable
+    Idempotency(IdempotencyError),
+    // Request body in an encoding we cannot read, or inflating past its limit
+    Compression(CompressionError),
+    // Over the route's URL, header or body size limits, or an unreadable body
+    Rejected(ApiError),
+    RoutingError,
+    BackendError,
+}
+
+// Implementations for error handling and conversions...
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use sha2::{Digest, Sha256};
+    use crate::models::config::{ApiKeyConfig, ApiKeyEntry, AuthConfig, BasicAuthConfig, BasicUser};
+
+    const API_KEY: &str = "key-123";
+    const PEER: &str = "203.0.113.7:50000";
+
+    fn route(authentication: AuthConfig) -> Route {
+        Route {
+            path: "/orders".into(),
+            methods: vec!["GET".into()],
+            authentication,
+            ..Default::default()
+        }
+    }
+
+    // Basic and API key authenticators, tried in `chain` order
+    fn chained(mode: AuthMode, chain: Vec<AuthMethod>, chain_policy: ChainPolicy) -> Route {
+        route(AuthConfig {
+            mode: Some(mode),
+            chain,
+            chain_policy,
+            basic: Some(BasicAuthConfig {
+                realm: "Orders".into(),
+                htpasswd_file: None,
+                users: vec![BasicUser {
+                    username: "ops".into(),
+                    password_hash: bcrypt::hash("hunter2", 4).unwrap(),
+                }],
+            }),
+            api_key: Some(ApiKeyConfig {
+                header: "x-api-key".into(),
+                keys: vec![ApiKeyEntry {
+                    consumer: "billing".into(),
+                    key_sha256: hex::encode(Sha256::digest(API_KEY)),
+                }],
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn gateway(route: &Route) -> GatewayService {
+        GatewayService::new(
+            RouteMatcher::new(vec![route.clone()]).unwrap(),
+            HybridRateLimiter::local(),
+            JwtValidator::new("http://localhost/jwks".parse().unwrap(), "issuer".into(), "audience".into()),
+            OAuthIntrospector::new("http://localhost/introspect".parse().unwrap(), "gateway".into(), "secret".into()),
+        )
+        .unwrap()
+    }
+
+    fn basic(user: &str, password: &str) -> (&'static str, String) {
+        ("authorization", format!("Basic {}", BASE64.encode(format!("{}:{}", user, password))))
+    }
+
+    fn api_key(key: &str) -> (&'static str, String) {
+        ("x-api-key", key.to_string())
+    }
+
+    async fn authenticate(route: &Route, headers: &[(&str, String)]) -> Result<String, GatewayError> {
+        let gateway = gateway(route);
+        let mut builder = Request::get("/orders");
+        for (name, value) in headers {
+            builder = builder.header(*name, value.as_str());
+        }
+        let mut req = gateway.build_api_request(builder.body(Body::empty()).unwrap(), PEER.parse().unwrap(), Some(route)).await?;
+        gateway.authenticate(route, &mut req).await?;
+        Ok(req.identity.expect("identity set on success").sub)
+    }
+
+    #[tokio::test]
+    async fn test_chain_first_success() {
+        let route = chained(AuthMode::Required, vec![AuthMethod::ApiKey, AuthMethod::Basic], ChainPolicy::FirstSuccess);
+
+        assert_eq!(authenticate(&route, &[basic("ops", "hunter2")]).await.unwrap(), "ops");
+        assert_eq!(authenticate(&route, &[api_key(API_KEY)]).await.unwrap(), "billing");
+        // The first method in the chain names the caller
+        assert_eq!(authenticate(&route, &[basic("ops", "hunter2"), api_key(API_KEY)]).await.unwrap(), "billing");
+
+        assert!(matches!(authenticate(&route, &[]).await, Err(GatewayError::AuthChallenge(_))));
+        assert!(matches!(authenticate(&route, &[basic("ops", "wrong")]).await, Err(GatewayError::AuthChallenge(_))));
+    }
+
+    #[tokio::test]
+    async fn test_optional_auth() {
+        let route = chained(AuthMode::Optional, Vec::new(), ChainPolicy::FirstSuccess);
+
+        assert_eq!(authenticate(&route, &[]).await.unwrap(), Claims::ANONYMOUS);
+        assert_eq!(authenticate(&route, &[basic("ops", "hunter2")]).await.unwrap(), "ops");
+        // Rejected credentials are not downgraded to anonymous
+        assert!(matches!(authenticate(&route, &[api_key("stolen")]).await, Err(GatewayError::Unauthorized)));
+    }
+
+    #[tokio::test]
+    async fn test_chain_all_must_pass() {
+        let route = chained(AuthMode::Required, vec![AuthMethod::Basic, AuthMethod::ApiKey], ChainPolicy::AllMustPass);
+
+        assert_eq!(authenticate(&route, &[basic("ops", "hunter2"), api_key(API_KEY)]).await.unwrap(), "ops");
+        assert!(authenticate(&route, &[basic("ops", "hunter2")]).await.is_err());
+        assert!(authenticate(&route, &[api_key(API_KEY)]).await.is_err());
+        assert!(authenticate(&route, &[basic("ops", "hunter2"), api_key("stolen")]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_routes_without_authenticators_use_jwt() {
+        let required = route(AuthConfig { mode: Some(AuthMode::Required), ..Default::default() });
+        assert!(matches!(authenticate(&required, &[]).await, Err(GatewayError::Unauthorized)));
+
+        // An optional route still checks a token that was presented
+        let optional = route(AuthConfig { mode: Some(AuthMode::Optional), ..Default::default() });
+        let bearer = ("authorization", "Bearer not-a-jwt".to_string());
+        assert!(matches!(authenticate(&optional, &[bearer]).await, Err(GatewayError::Unauthorized)));
+        assert_eq!(authenticate(&optional, &[]).await.unwrap(), Claims::ANONYMOUS);
+    }
+
+    #[tokio::test]
+    async fn test_shadow_policy_never_rejects() {
+        let policies = RateLimitPolicies::from_yaml(r#"
+policies:
+  enforced:
+    limit: 1
+    window: 1m
+  shadowed:
+    limit: 1
+    window: 1m
+    mode: shadow
+"#).unwrap();
+        let route = route(AuthConfig::default());
+        let gateway = gateway(&route).with_rate_limit_policies(policies).unwrap();
+        let req = gateway.build_api_request(Request::get("/orders").body(Body::empty()).unwrap(), PEER.parse().unwrap(), Some(&route)).await.unwrap();
+
+        let check = |name: &'static str| {
+            let (gateway, route, req) = (&gateway, &route, &req);
+            async move {
+                let policy = gateway.rate_limit_policies.resolve(Some(name), req);
+                let rules = gateway.rate_limit_policies.evaluate(policy.unwrap(), req);
+                gateway.check_rate_limits(route, policy, &rules, 1).await
+            }
+        };
+
+        assert!(check("enforced").await.unwrap().is_some());
+        assert!(matches!(check("enforced").await, Err(GatewayError::RateLimitExceeded(_))));
+
+        // Would-be rejections are only counted, and no RateLimit-* headers are sent
+        for _ in 0..3 {
+            assert!(check("shadowed").await.unwrap().is_none());
+        }
+    }
+}
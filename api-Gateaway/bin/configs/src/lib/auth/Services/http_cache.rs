@@ -0,0 +1,531 @@
+use chrono::DateTime;
+use hyper::header::{
+    HeaderName, HeaderValue, AGE, AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_LOCATION, DATE, ETAG,
+    EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, SET_COOKIE, VARY, WARNING,
+};
+use hyper::{Body, HeaderMap, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::models::{ApiRequest, ApiResponse};
+
+// Statuses a shared cache may store (RFC 9111 heuristically cacheable codes)
+const CACHEABLE_STATUSES: [u16; 11] = [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+// Connection-level headers are never replayed from the cache
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// Headers a 304 repeats from the stored response (RFC 9110 15.4.5)
+const NOT_MODIFIED_HEADERS: [HeaderName; 7] = [CACHE_CONTROL, CONTENT_LOCATION, DATE, ETAG, EXPIRES, LAST_MODIFIED, VARY];
+
+// Purge tags set by backends; stored with the entry but never sent to clients
+const CACHE_TAG: &str = "cache-tag";
+const SURROGATE_KEY: &str = "surrogate-key";
+
+// Warnings attached to stale responses (RFC 7234 5.5)
+const STALE_WARNING: &str = "110 - \"Response is Stale\"";
+const REVALIDATION_FAILED_WARNING: &str = "111 - \"Revalidation Failed\"";
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+// Cache-Control directives a shared cache acts on
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CacheControl {
+    pub max_age: Option<u64>,
+    pub s_maxage: Option<u64>,
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub private: bool,
+    pub public: bool,
+    pub must_revalidate: bool,
+    // RFC 5861 extensions
+    pub stale_while_revalidate: Option<u64>,
+    pub stale_if_error: Option<u64>,
+}
+
+impl CacheControl {
+    pub fn parse(headers: &HeaderMap) -> Self {
+        let mut cc = Self::default();
+        let directives = headers.get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+
+        for directive in directives {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name, Some(value.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            let seconds = || value.and_then(|v| v.parse().ok());
+
+            match name.trim().to_ascii_lowercase().as_str() {
+                "max-age" => cc.max_age = seconds(),
+                "s-maxage" => cc.s_maxage = seconds(),
+                "no-store" => cc.no_store = true,
+                "no-cache" => cc.no_cache = true,
+                "private" => cc.private = true,
+                "public" => cc.public = true,
+                "must-revalidate" | "proxy-revalidate" => cc.must_revalidate = true,
+                "stale-while-revalidate" => cc.stale_while_revalidate = seconds(),
+                "stale-if-error" => cc.stale_if_error = seconds(),
+                _ => {}
+            }
+        }
+        cc
+    }
+
+    // Freshness lifetime for a shared cache; s-maxage wins over max-age
+    pub fn shared_max_age(&self) -> Option<u64> {
+        self.s_maxage.or(self.max_age)
+    }
+}
+
+pub fn is_cacheable_request(req: &ApiRequest) -> bool {
+    (req.method == Method::GET || req.method == Method::HEAD) && !CacheControl::parse(&req.headers).no_store
+}
+
+// `no-cache` or `max-age=0` from the client forces revalidation of a stored copy
+pub fn requires_revalidation(request_headers: &HeaderMap) -> bool {
+    let cc = CacheControl::parse(request_headers);
+    cc.no_cache || cc.max_age == Some(0)
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+// HTTP-dates as unix seconds
+fn header_date(headers: &HeaderMap, name: &HeaderName) -> Option<i64> {
+    DateTime::parse_from_rfc2822(header_str(headers, name)?).ok().map(|date| date.timestamp())
+}
+
+pub fn is_hop_by_hop(name: &HeaderName) -> bool {
+    HOP_BY_HOP.contains(&name.as_str())
+}
+
+fn is_tag_header(name: &HeaderName) -> bool {
+    name == CACHE_TAG || name == SURROGATE_KEY
+}
+
+// Cache-Tag is comma separated, Surrogate-Key space separated
+fn take_tags(headers: &mut HeaderMap) -> Vec<String> {
+    let mut tags: Vec<String> = headers.get_all(CACHE_TAG)
+        .iter()
+        .chain(headers.get_all(SURROGATE_KEY).iter())
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect();
+    tags.sort();
+    tags.dedup();
+
+    headers.remove(CACHE_TAG);
+    headers.remove(SURROGATE_KEY);
+    tags
+}
+
+fn freshness(headers: &HeaderMap, cc: &CacheControl, default_ttl: Option<u64>) -> Option<u64> {
+    if cc.no_cache {
+        return Some(0);
+    }
+    if let Some(max_age) = cc.shared_max_age() {
+        return Some(max_age);
+    }
+    if let Some(expires) = header_date(headers, &EXPIRES) {
+        let date = header_date(headers, &DATE).unwrap_or(now() as i64);
+        return Some((expires - date).max(0) as u64);
+    }
+    default_ttl
+}
+
+// Lower-cased names of the request headers the response varies on
+fn vary_names(headers: &HeaderMap) -> Vec<String> {
+    let mut names: Vec<String> = headers.get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// Stale windows from the response; None leaves them to the route's defaults
+fn stale_windows(cc: &CacheControl) -> (Option<u64>, Option<u64>) {
+    if cc.must_revalidate || cc.no_cache {
+        return (Some(0), Some(0));
+    }
+    (cc.stale_while_revalidate, cc.stale_if_error)
+}
+
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+// A stored response, replayed for as long as it is fresh and revalidated after that
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub body: Vec<u8>,
+    // Unix seconds when the response was received or last revalidated
+    pub stored_at: u64,
+    // Age the response already had upstream
+    pub initial_age: u64,
+    // Freshness lifetime in seconds, the soft TTL
+    pub ttl: u64,
+    // How long past the soft TTL the entry may still be served, and under which condition
+    pub stale_while_revalidate: Option<u64>,
+    pub stale_if_error: Option<u64>,
+    pub vary: Vec<String>,
+    // Purge handles: the backend's tags and the request path
+    pub tags: Vec<String>,
+    pub path: String,
+}
+
+impl CachedResponse {
+    // Buffers `res` when a shared cache may store it, putting the buffered body back
+    pub async fn capture(
+        request_headers: &HeaderMap,
+        res: &mut ApiResponse,
+        default_ttl: Option<u64>,
+        max_bytes: usize,
+    ) -> Result<Option<Self>, hyper::Error> {
+        let tags = take_tags(&mut res.headers);
+        let cc = CacheControl::parse(&res.headers);
+        if !CACHEABLE_STATUSES.contains(&res.status.as_u16()) || cc.no_store || cc.private {
+            return Ok(None);
+        }
+        if res.headers.contains_key(SET_COOKIE) {
+            return Ok(None);
+        }
+        // Answers to authenticated requests are only shared when the backend allows it (RFC 9111 3.5)
+        if request_headers.contains_key(AUTHORIZATION) && !(cc.public || cc.s_maxage.is_some() || cc.must_revalidate) {
+            return Ok(None);
+        }
+
+        let vary = vary_names(&res.headers);
+        if vary.iter().any(|name| name == "*") {
+            return Ok(None);
+        }
+
+        if res.known_length().map_or(true, |length| length > max_bytes as u64) {
+            return Ok(None);
+        }
+
+        let has_validator = res.headers.contains_key(ETAG) || res.headers.contains_key(LAST_MODIFIED);
+        let Some(ttl) = freshness(&res.headers, &cc, default_ttl).filter(|ttl| *ttl > 0 || has_validator) else {
+            return Ok(None);
+        };
+
+        let body = hyper::body::to_bytes(std::mem::take(&mut res.body)).await?;
+        res.body = Body::from(body.clone());
+        let (stale_while_revalidate, stale_if_error) = stale_windows(&cc);
+
+        Ok(Some(Self {
+            status: res.status.as_u16(),
+            headers: Self::encode_headers(&res.headers),
+            body: body.to_vec(),
+            stored_at: now(),
+            initial_age: header_str(&res.headers, &AGE).and_then(|age| age.parse().ok()).unwrap_or_default(),
+            ttl,
+            stale_while_revalidate,
+            stale_if_error,
+            vary,
+            tags,
+            path: String::new(),
+        }))
+    }
+
+    fn encode_headers(headers: &HeaderMap) -> Vec<(String, Vec<u8>)> {
+        headers.iter()
+            .filter(|(name, _)| !is_hop_by_hop(name))
+            .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+            .collect()
+    }
+
+    pub fn header_map(&self) -> HeaderMap {
+        self.headers.iter()
+            .filter_map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes()).ok()?;
+                let value = HeaderValue::from_bytes(value).ok()?;
+                Some((name, value))
+            })
+            .fold(HeaderMap::new(), |mut headers, (name, value)| {
+                headers.append(name, value);
+                headers
+            })
+    }
+
+    fn header(&self, name: &HeaderName) -> Option<&[u8]> {
+        self.headers.iter()
+            .find(|(n, _)| n == name.as_str())
+            .map(|(_, value)| value.as_slice())
+    }
+
+    // Approximate memory held by the entry
+    pub fn size(&self) -> usize {
+        self.body.len() + self.headers.iter().map(|(name, value)| name.len() + value.len()).sum::<usize>()
+    }
+
+    pub fn age(&self, now: u64) -> u64 {
+        self.initial_age + now.saturating_sub(self.stored_at)
+    }
+
+    pub fn is_fresh(&self, now: u64) -> bool {
+        self.age(now) < self.ttl
+    }
+
+    // Route defaults for the stale windows the backend left open
+    pub fn with_stale_defaults(mut self, stale_while_revalidate: Option<u64>, stale_if_error: Option<u64>) -> Self {
+        self.stale_while_revalidate = self.stale_while_revalidate.or(stale_while_revalidate);
+        self.stale_if_error = self.stale_if_error.or(stale_if_error);
+        self
+    }
+
+    // Lifetime after which the entry can no longer be served in any case
+    pub fn hard_ttl(&self) -> u64 {
+        let stale = self.stale_while_revalidate.unwrap_or_default().max(self.stale_if_error.unwrap_or_default());
+        self.ttl.saturating_add(stale)
+    }
+
+    pub fn expires_in(&self, now: u64) -> u64 {
+        self.hard_ttl().saturating_sub(self.age(now))
+    }
+
+    // Stale, but recent enough to answer while a refresh runs in the background
+    pub fn is_stale_while_revalidate(&self, now: u64) -> bool {
+        let age = self.age(now);
+        age >= self.ttl && age < self.ttl.saturating_add(self.stale_while_revalidate.unwrap_or_default())
+    }
+
+    // Recent enough to stand in for a failing backend
+    pub fn is_usable_on_error(&self, now: u64) -> bool {
+        self.age(now) < self.ttl.saturating_add(self.stale_if_error.unwrap_or_default())
+    }
+
+    // Makes the upstream request conditional so an unchanged resource only costs a 304
+    pub fn add_validators(&self, request_headers: &mut HeaderMap) {
+        request_headers.remove(IF_NONE_MATCH);
+        request_headers.remove(IF_MODIFIED_SINCE);
+
+        let validators = [(ETAG, IF_NONE_MATCH), (LAST_MODIFIED, IF_MODIFIED_SINCE)];
+        for (stored, conditional) in validators {
+            if let Some(value) = self.header(&stored).and_then(|v| HeaderValue::from_bytes(v).ok()) {
+                request_headers.insert(conditional, value);
+            }
+        }
+    }
+
+    // Entry refreshed by a 304 from upstream, with the headers it sent merged in
+    pub fn revalidated(&self, not_modified: &HeaderMap, default_ttl: Option<u64>) -> Self {
+        let mut headers = self.header_map();
+        for name in not_modified.keys().filter(|name| !is_hop_by_hop(name) && !is_tag_header(name) && **name != CONTENT_LENGTH) {
+            headers.remove(name);
+            for value in not_modified.get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+
+        let cc = CacheControl::parse(&headers);
+        let (stale_while_revalidate, stale_if_error) = stale_windows(&cc);
+        Self {
+            status: self.status,
+            headers: Self::encode_headers(&headers),
+            body: self.body.clone(),
+            stored_at: now(),
+            initial_age: header_str(not_modified, &AGE).and_then(|age| age.parse().ok()).unwrap_or_default(),
+            ttl: freshness(&headers, &cc, default_ttl).unwrap_or_default(),
+            stale_while_revalidate,
+            stale_if_error,
+            vary: self.vary.clone(),
+            tags: self.tags.clone(),
+            path: self.path.clone(),
+        }
+    }
+
+    // Whether the client's conditional headers match this entry
+    fn not_modified_for(&self, request_headers: &HeaderMap) -> bool {
+        if self.status != StatusCode::OK.as_u16() {
+            return false;
+        }
+
+        // If-None-Match takes precedence over If-Modified-Since
+        if let Some(if_none_match) = header_str(request_headers, &IF_NONE_MATCH) {
+            let Some(etag) = self.header(&ETAG).and_then(|v| std::str::from_utf8(v).ok()) else {
+                return false;
+            };
+            return if_none_match.split(',').map(str::trim).any(|tag| tag == "*" || weak_eq(tag, etag));
+        }
+
+        let last_modified = self.header(&LAST_MODIFIED)
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok());
+        match (header_date(request_headers, &IF_MODIFIED_SINCE), last_modified) {
+            (Some(since), Some(modified)) => modified.timestamp() <= since,
+            _ => false,
+        }
+    }
+
+    // Full response, or 304 when the client already holds this version
+    pub fn respond(&self, request_headers: &HeaderMap, now: u64) -> ApiResponse {
+        let headers = self.header_map();
+        let mut response = if self.not_modified_for(request_headers) {
+            let mut response = ApiResponse::new(StatusCode::NOT_MODIFIED);
+            for name in &NOT_MODIFIED_HEADERS {
+                for value in headers.get_all(name) {
+                    response.headers.append(name.clone(), value.clone());
+                }
+            }
+            response
+        } else {
+            let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+            let mut response = ApiResponse::new(status).with_body(self.body.clone());
+            response.headers = headers;
+            response
+        };
+
+        response.headers.insert(AGE, HeaderValue::from(self.age(now)));
+        response
+    }
+
+    // Stale copy, with a Warning saying whether the backend failed to revalidate it
+    pub fn respond_stale(&self, request_headers: &HeaderMap, now: u64, revalidation_failed: bool) -> ApiResponse {
+        let mut response = self.respond(request_headers, now);
+        let warning = if revalidation_failed { REVALIDATION_FAILED_WARNING } else { STALE_WARNING };
+        response.headers.append(WARNING, HeaderValue::from_static(warning));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs.iter()
+            .map(|(name, value)| (HeaderName::from_static(name), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    fn response(pairs: &[(&'static str, &'static str)], body: &'static str) -> ApiResponse {
+        let mut response = ApiResponse::new(StatusCode::OK).with_body(body);
+        response.headers = headers(pairs);
+        response.headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+        response
+    }
+
+    #[test]
+    fn test_parse_cache_control() {
+        let cc = CacheControl::parse(&headers(&[("cache-control", "public, max-age=60, s-maxage=\"300\"")]));
+        assert!(cc.public);
+        assert_eq!(cc.shared_max_age(), Some(300));
+
+        let cc = CacheControl::parse(&headers(&[("cache-control", "No-Store")]));
+        assert!(cc.no_store);
+    }
+
+    #[tokio::test]
+    async fn test_capture_honours_cache_control() {
+        let none = HeaderMap::new();
+
+        let mut res = response(&[("cache-control", "max-age=60"), ("vary", "Accept-Encoding, Accept")], "hello");
+        let entry = CachedResponse::capture(&none, &mut res, None, 1024).await.unwrap().unwrap();
+        assert_eq!(entry.ttl, 60);
+        assert_eq!(entry.vary, vec!["accept", "accept-encoding"]);
+        assert!(entry.tags.is_empty());
+        assert_eq!(hyper::body::to_bytes(res.body).await.unwrap(), "hello");
+
+        for cache_control in ["private, max-age=60", "no-store"] {
+            let mut res = response(&[("cache-control", cache_control)], "hello");
+            assert!(CachedResponse::capture(&none, &mut res, Some(60), 1024).await.unwrap().is_none());
+        }
+
+        // Authenticated requests need an explicit opt-in from the backend
+        let authorized = headers(&[("authorization", "Bearer token")]);
+        let mut res = response(&[("cache-control", "max-age=60")], "hello");
+        assert!(CachedResponse::capture(&authorized, &mut res, None, 1024).await.unwrap().is_none());
+        let mut res = response(&[("cache-control", "s-maxage=60")], "hello");
+        assert!(CachedResponse::capture(&authorized, &mut res, None, 1024).await.unwrap().is_some());
+
+        // Too large to buffer
+        let mut res = response(&[("cache-control", "max-age=60")], "hello");
+        assert!(CachedResponse::capture(&none, &mut res, None, 4).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let mut res = response(&[("cache-control", "no-cache"), ("etag", "\"v1\"")], "hello");
+        let entry = CachedResponse::capture(&HeaderMap::new(), &mut res, None, 1024).await.unwrap().unwrap();
+        assert!(!entry.is_fresh(now()));
+
+        let mut upstream = HeaderMap::new();
+        entry.add_validators(&mut upstream);
+        assert_eq!(upstream[IF_NONE_MATCH], "\"v1\"");
+
+        let refreshed = entry.revalidated(&headers(&[("cache-control", "max-age=30")]), None);
+        assert!(refreshed.is_fresh(now()));
+        assert_eq!(refreshed.header(&ETAG), Some(&b"\"v1\""[..]));
+
+        let client = headers(&[("if-none-match", "W/\"v0\", W/\"v1\"")]);
+        let not_modified = refreshed.respond(&client, now());
+        assert_eq!(not_modified.status, StatusCode::NOT_MODIFIED);
+        assert_eq!(not_modified.headers[ETAG], "\"v1\"");
+
+        let full = refreshed.respond(&HeaderMap::new(), now());
+        assert_eq!(full.status, StatusCode::OK);
+        assert!(full.headers.contains_key(AGE));
+    }
+
+    #[tokio::test]
+    async fn test_capture_takes_purge_tags() {
+        let mut res = response(&[
+            ("cache-control", "max-age=60"),
+            ("cache-tag", "product-42, catalog"),
+            ("surrogate-key", "catalog  pricing"),
+        ], "hello");
+        let entry = CachedResponse::capture(&HeaderMap::new(), &mut res, None, 1024).await.unwrap().unwrap();
+        assert_eq!(entry.tags, vec!["catalog", "pricing", "product-42"]);
+        assert!(!res.headers.contains_key(CACHE_TAG));
+        assert!(!entry.header_map().contains_key(SURROGATE_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_stale_windows() {
+        let mut res = response(&[("cache-control", "max-age=60, stale-while-revalidate=30")], "hello");
+        let entry = CachedResponse::capture(&HeaderMap::new(), &mut res, None, 1024).await.unwrap().unwrap()
+            .with_stale_defaults(Some(10), Some(300));
+        assert_eq!(entry.stale_while_revalidate, Some(30));
+        assert_eq!(entry.hard_ttl(), 360);
+
+        let now = entry.stored_at;
+        assert!(!entry.is_stale_while_revalidate(now + 30));
+        assert!(entry.is_stale_while_revalidate(now + 60));
+        assert!(!entry.is_stale_while_revalidate(now + 90));
+        assert!(entry.is_usable_on_error(now + 200));
+        assert!(!entry.is_usable_on_error(now + 360));
+
+        let stale = entry.respond_stale(&HeaderMap::new(), now + 200, true);
+        assert_eq!(stale.headers[WARNING], "111 - \"Revalidation Failed\"");
+        assert_eq!(stale.headers[AGE], "200");
+
+        // must-revalidate rules out serving stale copies
+        let mut res = response(&[("cache-control", "max-age=60, must-revalidate")], "hello");
+        let entry = CachedResponse::capture(&HeaderMap::new(), &mut res, None, 1024).await.unwrap().unwrap()
+            .with_stale_defaults(Some(10), Some(300));
+        assert_eq!(entry.hard_ttl(), 60);
+    }
+}
@@ -0,0 +1,388 @@
+use futures::{stream, StreamExt};
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Body, StatusCode};
+use redis::{RedisError, RedisResult, Script};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use thiserror::Error;
+use crate::models::{ApiRequest, ApiResponse, config::IdempotencyConfig};
+use crate::routing::matcher::Route;
+use crate::services::cache_key::CacheKeyBuilder;
+use crate::services::http_cache;
+use crate::rate_limiting::redis_store::RedisConnection;
+
+// Set on replayed responses so clients can tell them from first answers
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+const MAX_KEY_LENGTH: usize = 255;
+
+// Claims the key for this request, or returns the fingerprint and stored
+// response (false while in flight, empty when it was too large to keep) of
+// whoever claimed it first
+const CLAIM_KEY: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 1 then
+    return redis.call('HMGET', KEYS[1], 'fingerprint', 'response')
+end
+redis.call('HSET', KEYS[1], 'token', ARGV[1], 'fingerprint', ARGV[2])
+redis.call('PEXPIRE', KEYS[1], ARGV[3])
+return false
+";
+
+// Stores the response only while the claim is still ours
+const COMPLETE_KEY: &str = r"
+if redis.call('HGET', KEYS[1], 'token') ~= ARGV[1] then
+    return 0
+end
+redis.call('HSET', KEYS[1], 'response', ARGV[2])
+redis.call('HDEL', KEYS[1], 'token')
+redis.call('EXPIRE', KEYS[1], ARGV[3])
+return 1
+";
+
+const RELEASE_KEY: &str = r"
+if redis.call('HGET', KEYS[1], 'token') == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+#[derive(Debug, Error)]
+pub enum IdempotencyError {
+    #[error("Missing {0} header")]
+    MissingKey(String),
+    #[error("Idempotency key must be 1 to {MAX_KEY_LENGTH} visible characters")]
+    InvalidKey,
+    #[error("A request with this idempotency key is still being processed")]
+    InProgress,
+    #[error("Idempotency key was already used with a different request")]
+    Mismatch,
+    #[error("A request with this idempotency key was already processed and its response cannot be replayed")]
+    NotReplayable,
+    #[error("Idempotency store error: {0}")]
+    Store(#[from] RedisError),
+    #[error("Cannot encode stored response: {0}")]
+    Encoding(#[from] bincode::Error),
+}
+
+impl IdempotencyError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            IdempotencyError::MissingKey(_) | IdempotencyError::InvalidKey => StatusCode::BAD_REQUEST,
+            IdempotencyError::InProgress | IdempotencyError::NotReplayable => StatusCode::CONFLICT,
+            IdempotencyError::Mismatch => StatusCode::UNPROCESSABLE_ENTITY,
+            // Forwarding without the check could charge a retried payment twice
+            IdempotencyError::Store(_) | IdempotencyError::Encoding(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredResponse {
+    status: u16,
+    headers: Vec<(String, Vec<u8>)>,
+    body: Vec<u8>,
+}
+
+impl StoredResponse {
+    fn into_response(self) -> ApiResponse {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+        let mut response = ApiResponse::new(status).with_body(self.body);
+        for (name, value) in self.headers {
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_bytes(&value)) {
+                response.headers.append(name, value);
+            }
+        }
+        response.headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+// A request covered by an idempotency key
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdempotentRequest {
+    pub key: String,
+    pub fingerprint: String,
+}
+
+pub enum Claim {
+    // First request with the key; forward it and complete the claim with the answer
+    Acquired(IdempotencyClaim),
+    Replay(ApiResponse),
+}
+
+// Held while the first request is in flight; dropping it without completing
+// frees the key so the client can retry
+pub struct IdempotencyClaim {
+    conn: RedisConnection,
+    key: String,
+    token: String,
+    window: Duration,
+    max_response_bytes: usize,
+    done: bool,
+}
+
+impl IdempotencyClaim {
+    // Stores the answer for replays. A body larger than `max_response_bytes` is
+    // passed on without being kept, and the key stays completed but not
+    // replayable, so retries are turned away rather than processed again
+    pub async fn complete(mut self, res: &mut ApiResponse) {
+        let body = if res.known_length().is_some_and(|length| length > self.max_response_bytes as u64) {
+            None
+        } else {
+            match self.buffer(res).await {
+                Ok(body) => body,
+                Err(e) => return log::warn!("Cannot read response for idempotency key {}: {}", self.key, e),
+            }
+        };
+
+        let data = match body {
+            Some(body) => {
+                let stored = StoredResponse {
+                    status: res.status.as_u16(),
+                    headers: res.headers.iter()
+                        .filter(|(name, _)| !http_cache::is_hop_by_hop(name))
+                        .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+                        .collect(),
+                    body: body.to_vec(),
+                };
+                match bincode::serialize(&stored) {
+                    Ok(data) => data,
+                    Err(e) => return log::warn!("Cannot encode response for idempotency key {}: {}", self.key, e),
+                }
+            }
+            None => {
+                log::info!("Response for idempotency key {} is too large to replay", self.key);
+                Vec::new()
+            }
+        };
+        match self.store(data).await {
+            Ok(true) => self.done = true,
+            Ok(false) => log::warn!("Idempotency key {} expired before its response was stored", self.key),
+            Err(e) => log::warn!("Cannot store response for idempotency key {}: {}", self.key, e),
+        }
+    }
+
+    // The body when it fits in `max_response_bytes`, None otherwise; either way
+    // `res` is left with the complete body
+    async fn buffer(&self, res: &mut ApiResponse) -> Result<Option<Bytes>, hyper::Error> {
+        let mut body = std::mem::take(&mut res.body);
+        let mut chunks = Vec::new();
+        let mut size = 0;
+        while let Some(chunk) = body.data().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    // The client sees the body break off where the backend's did
+                    let failure = std::io::Error::new(std::io::ErrorKind::Other, e.to_string());
+                    let read = stream::iter(chunks.into_iter().map(Ok));
+                    res.body = Body::wrap_stream(read.chain(stream::once(async { Err(failure) })));
+                    return Err(e);
+                }
+            };
+            size += chunk.len();
+            chunks.push(chunk);
+            if size > self.max_response_bytes {
+                let read = stream::iter(chunks.into_iter().map(Ok));
+                res.body = Body::wrap_stream(read.chain(body));
+                return Ok(None);
+            }
+        }
+
+        let body = Bytes::from(chunks.concat());
+        res.body = Body::from(body.clone());
+        Ok(Some(body))
+    }
+
+    // Empty `data` marks the key completed without a response to replay
+    async fn store(&mut self, data: Vec<u8>) -> Result<bool, IdempotencyError> {
+        let stored: i64 = Script::new(COMPLETE_KEY)
+            .key(&self.key)
+            .arg(&self.token)
+            .arg(data)
+            .arg(self.window.as_secs())
+            .invoke_async(&mut self.conn)
+            .await?;
+        Ok(stored == 1)
+    }
+}
+
+impl Drop for IdempotencyClaim {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        let (mut conn, key, token) = (self.conn.clone(), std::mem::take(&mut self.key), std::mem::take(&mut self.token));
+        tokio::spawn(async move {
+            // An unreleased claim still expires after its lock TTL
+            let result: RedisResult<i64> = Script::new(RELEASE_KEY)
+                .key(&key)
+                .arg(&token)
+                .invoke_async(&mut conn)
+                .await;
+            if let Err(e) = result {
+                log::warn!("Failed to release idempotency key {}: {}", key, e);
+            }
+        });
+    }
+}
+
+// Remembers answers to unsafe requests by their Idempotency-Key, in Redis so
+// retries landing on another instance are recognised too
+pub struct IdempotencyStore {
+    redis: RedisConnection,
+}
+
+impl IdempotencyStore {
+    pub fn new(redis: RedisConnection) -> Self {
+        Self { redis }
+    }
+
+    // None when the route's policy does not cover the request
+    pub fn request_key(route: &Route, config: &IdempotencyConfig, req: &ApiRequest) -> Result<Option<IdempotentRequest>, IdempotencyError> {
+        if !config.methods.iter().any(|method| method.eq_ignore_ascii_case(req.method.as_str())) {
+            return Ok(None);
+        }
+        let Some(value) = req.headers.get(config.header.as_str()) else {
+            if config.required {
+                return Err(IdempotencyError::MissingKey(config.header.clone()));
+            }
+            return Ok(None);
+        };
+        let key = value.to_str().map_err(|_| IdempotencyError::InvalidKey)?.trim();
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+            return Err(IdempotencyError::InvalidKey);
+        }
+
+        // Keys are only unique per caller, so two clients picking the same one never collide
+        let caller = CacheKeyBuilder::user_scope(req).unwrap_or_else(|| "anonymous".to_string());
+        let mut hasher = Sha256::new();
+        hasher.update(req.method.as_str());
+        hasher.update([0]);
+        hasher.update(req.uri.path_and_query().map(|pq| pq.as_str()).unwrap_or_default());
+        hasher.update([0]);
+        hasher.update(&req.body);
+
+        Ok(Some(IdempotentRequest {
+            key: format!("idempotency:{}:{}:{}", route.path, caller, key),
+            fingerprint: hex::encode(hasher.finalize()),
+        }))
+    }
+
+    pub async fn begin(&self, route: &Route, config: &IdempotencyConfig, req: &ApiRequest) -> Result<Option<Claim>, IdempotencyError> {
+        let Some(request) = Self::request_key(route, config, req)? else { return Ok(None) };
+
+        let mut conn = self.redis.clone();
+        let token = uuid::Uuid::new_v4().to_string();
+        let lock_ttl = Duration::from_secs(config.lock_ttl_seconds);
+        let existing: Option<(Option<String>, Option<Vec<u8>>)> = Script::new(CLAIM_KEY)
+            .key(&request.key)
+            .arg(&token)
+            .arg(&request.fingerprint)
+            .arg(lock_ttl.as_millis() as u64)
+            .invoke_async(&mut conn)
+            .await?;
+
+        let Some((fingerprint, response)) = existing else {
+            return Ok(Some(Claim::Acquired(IdempotencyClaim {
+                conn,
+                key: request.key,
+                token,
+                window: Duration::from_secs(config.window_seconds),
+                max_response_bytes: config.max_response_bytes,
+                done: false,
+            })));
+        };
+
+        if fingerprint.as_deref() != Some(request.fingerprint.as_str()) {
+            return Err(IdempotencyError::Mismatch);
+        }
+        match response {
+            Some(data) if data.is_empty() => Err(IdempotencyError::NotReplayable),
+            Some(data) => {
+                log::debug!("Replaying stored response for {}", request.key);
+                let stored: StoredResponse = bincode::deserialize(&data)?;
+                Ok(Some(Claim::Replay(stored.into_response())))
+            }
+            None => Err(IdempotencyError::InProgress),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{body::Bytes, Method};
+
+    fn config(required: bool) -> IdempotencyConfig {
+        IdempotencyConfig {
+            header: "Idempotency-Key".into(),
+            required,
+            methods: vec!["POST".into(), "PATCH".into()],
+            window_seconds: 86400,
+            lock_ttl_seconds: 60,
+            max_response_bytes: 1024 * 1024,
+        }
+    }
+
+    fn request(method: Method, key: Option<&str>, body: &'static str) -> ApiRequest {
+        let mut req = ApiRequest::for_test(method, "/payments?currency=eur");
+        if let Some(key) = key {
+            req.headers.insert("idempotency-key", key.parse().unwrap());
+        }
+        req.body = Bytes::from(body);
+        req
+    }
+
+    #[test]
+    fn test_request_key() {
+        let route = Route { path: "/payments".into(), ..Default::default() };
+
+        let first = IdempotencyStore::request_key(&route, &config(false), &request(Method::POST, Some("abc"), "{\"amount\":10}")).unwrap().unwrap();
+        let retry = IdempotencyStore::request_key(&route, &config(false), &request(Method::POST, Some("abc"), "{\"amount\":10}")).unwrap().unwrap();
+        let changed = IdempotencyStore::request_key(&route, &config(false), &request(Method::POST, Some("abc"), "{\"amount\":20}")).unwrap().unwrap();
+        assert_eq!(first, retry);
+        assert_eq!(first.key, "idempotency:/payments:anonymous:abc");
+        assert_ne!(first.fingerprint, changed.fingerprint);
+
+        let mut bob = request(Method::POST, Some("abc"), "{\"amount\":10}");
+        bob.headers.insert(hyper::header::AUTHORIZATION, "Bearer bob".parse().unwrap());
+        assert_ne!(IdempotencyStore::request_key(&route, &config(false), &bob).unwrap().unwrap().key, first.key);
+    }
+
+    #[test]
+    fn test_uncovered_and_invalid_requests() {
+        let route = Route::default();
+        assert!(IdempotencyStore::request_key(&route, &config(true), &request(Method::GET, None, "")).unwrap().is_none());
+        assert!(IdempotencyStore::request_key(&route, &config(false), &request(Method::POST, None, "")).unwrap().is_none());
+
+        let missing = IdempotencyStore::request_key(&route, &config(true), &request(Method::POST, None, ""));
+        assert!(matches!(missing, Err(IdempotencyError::MissingKey(header)) if header == "Idempotency-Key"));
+        let too_long = "k".repeat(MAX_KEY_LENGTH + 1);
+        let invalid = IdempotencyStore::request_key(&route, &config(true), &request(Method::PATCH, Some(too_long.as_str()), ""));
+        assert_eq!(invalid.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_is_passed_on_unstored() {
+        let claim = IdempotencyClaim {
+            // Connected lazily, never used here
+            conn: RedisConnection::new(redis::Client::open("redis://127.0.0.1:1").unwrap()),
+            key: "idempotency:/payments:anonymous:abc".into(),
+            token: "token".into(),
+            window: Duration::from_secs(60),
+            max_response_bytes: 8,
+            done: true,
+        };
+
+        let chunks: Vec<Result<&str, std::io::Error>> = vec![Ok("12345"), Ok("67890"), Ok("abc")];
+        let mut res = ApiResponse::new(StatusCode::OK).with_body(Body::wrap_stream(futures::stream::iter(chunks)));
+        assert!(claim.buffer(&mut res).await.unwrap().is_none());
+        assert_eq!(&hyper::body::to_bytes(res.body).await.unwrap()[..], b"1234567890abc");
+
+        let mut res = ApiResponse::new(StatusCode::OK).with_body("1234");
+        assert_eq!(&claim.buffer(&mut res).await.unwrap().unwrap()[..], b"1234");
+        assert_eq!(&hyper::body::to_bytes(res.body).await.unwrap()[..], b"1234");
+    }
+}
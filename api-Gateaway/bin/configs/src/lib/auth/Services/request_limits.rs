@@ -0,0 +1,131 @@
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::CONTENT_LENGTH;
+use hyper::http::request::Parts;
+use hyper::{Body, Request};
+use crate::models::config::RequestLimitsConfig;
+use crate::routing::matcher::Route;
+use crate::utils::error::ApiError;
+
+// Body limit of routes that set none, unless configured otherwise
+pub const DEFAULT_MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+fn rejected(route: &str, reason: &'static str, error: ApiError) -> ApiError {
+    log::debug!("Rejected request to {}: {}", route, error);
+    metrics::increment_counter!("gateway_requests_rejected_total", "route" => route.to_string(), "reason" => reason);
+    error
+}
+
+// URL and header sizes, known before any of the body arrives
+fn check_head(route: &str, parts: &Parts, limits: &RequestLimitsConfig) -> Result<(), ApiError> {
+    if let Some(max) = limits.max_url_length {
+        let length = parts.uri.path_and_query().map_or(0, |pq| pq.as_str().len());
+        if length > max {
+            return Err(rejected(route, "url_length", ApiError::UriTooLong));
+        }
+    }
+    if let Some(max) = limits.max_header_bytes {
+        let size: usize = parts.headers.iter().map(|(name, value)| name.as_str().len() + value.len()).sum();
+        if size > max {
+            return Err(rejected(route, "header_size", ApiError::HeaderFieldsTooLarge));
+        }
+    }
+    Ok(())
+}
+
+// Buffers the body, giving up as soon as it is known to exceed `max`; what is
+// left of an oversized upload is never read
+async fn read_body(route: &str, headers: &hyper::HeaderMap, mut body: Body, max: usize) -> Result<Bytes, ApiError> {
+    let declared = headers.get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > max as u64) {
+        return Err(rejected(route, "body_size", ApiError::PayloadTooLarge));
+    }
+
+    let mut buffer = Vec::with_capacity(declared.map_or(0, |length| length as usize));
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| ApiError::BadRequest(format!("Cannot read request body: {}", e)))?;
+        if buffer.len() + chunk.len() > max {
+            return Err(rejected(route, "body_size", ApiError::PayloadTooLarge));
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(buffer))
+}
+
+// Splits the request into its head and buffered body, enforcing the limits of
+// the route it was matched to; bodies are never read unbounded, routes without
+// a body limit of their own (and unrouted requests) get `default_max_body_bytes`
+pub async fn read_request(req: Request<Body>, route: Option<&Route>, default_max_body_bytes: usize) -> Result<(Parts, Bytes), ApiError> {
+    let (parts, body) = req.into_parts();
+    let limits = route.and_then(|route| Some((route.path.as_str(), route.limits.as_ref()?)));
+    if let Some((route, limits)) = limits {
+        check_head(route, &parts, limits)?;
+    }
+
+    let path = route.map_or("unrouted", |route| route.path.as_str());
+    let max = limits.and_then(|(_, limits)| limits.max_body_bytes).unwrap_or(default_max_body_bytes);
+    let body = read_body(path, &parts.headers, body, max).await?;
+    Ok((parts, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::StatusCode;
+
+    fn route(limits: RequestLimitsConfig) -> Route {
+        Route { path: "/uploads".into(), limits: Some(limits), ..Default::default() }
+    }
+
+    fn limits() -> RequestLimitsConfig {
+        RequestLimitsConfig {
+            max_body_bytes: Some(8),
+            max_header_bytes: Some(64),
+            max_url_length: Some(32),
+        }
+    }
+
+    const DEFAULT_MAX: usize = 64;
+
+    #[tokio::test]
+    async fn test_head_limits() {
+        let route = route(limits());
+        let long_url = Request::post(format!("/uploads?name={}", "x".repeat(32))).body(Body::empty()).unwrap();
+        let err = read_request(long_url, Some(&route), DEFAULT_MAX).await.unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::URI_TOO_LONG);
+
+        let big_headers = Request::post("/uploads").header("x-padding", "x".repeat(64)).body(Body::empty()).unwrap();
+        let err = read_request(big_headers, Some(&route), DEFAULT_MAX).await.unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+
+        let ok = Request::post("/uploads?name=a").body(Body::from("12345678")).unwrap();
+        let (_, body) = read_request(ok, Some(&route), DEFAULT_MAX).await.unwrap();
+        assert_eq!(&body[..], b"12345678");
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_while_streaming() {
+        let route = route(limits());
+
+        // Declared too large: rejected before anything is read
+        let declared = Request::post("/uploads").header(CONTENT_LENGTH, "1000").body(Body::from("1")).unwrap();
+        let err = read_request(declared, Some(&route), DEFAULT_MAX).await.unwrap_err();
+        assert!(matches!(err, ApiError::PayloadTooLarge));
+
+        // Chunked: rejected at the chunk that crosses the limit, the rest stays unread
+        let chunks: Vec<Result<&str, std::io::Error>> = vec![Ok("12345"), Ok("67890")];
+        let body = Body::wrap_stream(futures::stream::iter(chunks));
+        let err = read_request(Request::post("/uploads").body(body).unwrap(), Some(&route), DEFAULT_MAX).await.unwrap_err();
+        assert!(matches!(err, ApiError::PayloadTooLarge));
+
+        // Routes without a body limit, and unrouted requests, get the default one
+        let unlimited = Route::default();
+        let req = Request::post("/uploads").body(Body::from("x".repeat(DEFAULT_MAX))).unwrap();
+        assert_eq!(read_request(req, Some(&unlimited), DEFAULT_MAX).await.unwrap().1.len(), DEFAULT_MAX);
+        for route in [Some(&unlimited), None] {
+            let req = Request::post("/uploads").body(Body::from("x".repeat(DEFAULT_MAX + 1))).unwrap();
+            assert!(matches!(read_request(req, route, DEFAULT_MAX).await, Err(ApiError::PayloadTooLarge)));
+        }
+    }
+}
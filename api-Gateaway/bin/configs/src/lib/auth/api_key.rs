@@ -0,0 +1,54 @@
+use async_trait::async_trait;
+use hyper::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::auth::jwt::{AuthError, Authenticator, Claims};
+use crate::models::config::ApiKeyConfig;
+
+pub struct ApiKeyAuthenticator {
+    header: String,
+    // sha256(key) -> consumer
+    consumers: HashMap<String, String>,
+}
+
+impl ApiKeyAuthenticator {
+    pub fn new(config: &ApiKeyConfig) -> Self {
+        let consumers = config.keys.iter()
+            .map(|entry| (entry.key_sha256.to_ascii_lowercase(), entry.consumer.clone()))
+            .collect();
+
+        Self {
+            header: config.header.clone(),
+            consumers,
+        }
+    }
+
+    pub fn extract<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        headers.get(self.header.as_str()).and_then(|h| h.to_str().ok())
+    }
+}
+
+#[async_trait]
+impl Authenticator for ApiKeyAuthenticator {
+    async fn validate_token(&self, token: &str) -> Result<Claims, AuthError> {
+        // Only key digests are configured, so plaintext keys never sit in config files
+        let digest = hex::encode(Sha256::digest(token.trim().as_bytes()));
+        let consumer = self.consumers.get(&digest).ok_or(AuthError::InvalidCredentials)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as usize;
+
+        Ok(Claims {
+            sub: consumer.clone(),
+            exp: now,
+            iat: now,
+            iss: "gateway-api-key".into(),
+            aud: String::new(),
+            scope: String::new(),
+            extra: HashMap::new(),
+        })
+    }
+}
@@ -0,0 +1,222 @@
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hyper::header::HeaderValue;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use crate::auth::jwt::{AuthError, Authenticator, Claims};
+use crate::models::config::BasicAuthConfig;
+
+// How often the htpasswd file's mtime is checked for changes
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// Lifetime of the claims issued for a verified request
+const CLAIMS_TTL: usize = 300;
+
+struct HtpasswdState {
+    users: HashMap<String, String>,
+    modified: Option<SystemTime>,
+    last_checked: Instant,
+}
+
+pub struct BasicAuthenticator {
+    realm: String,
+    static_users: HashMap<String, String>,
+    htpasswd_file: Option<PathBuf>,
+    state: RwLock<HtpasswdState>,
+    // Checked for unknown users so they take as long to reject as wrong passwords
+    dummy_hash: String,
+}
+
+impl BasicAuthenticator {
+    pub fn new(config: &BasicAuthConfig) -> Result<Self, AuthError> {
+        let static_users = config.users.iter()
+            .map(|u| (u.username.clone(), u.password_hash.clone()))
+            .collect();
+
+        let (users, modified) = match &config.htpasswd_file {
+            Some(path) => (
+                parse_htpasswd(&std::fs::read_to_string(path)?),
+                std::fs::metadata(path)?.modified().ok(),
+            ),
+            None => (HashMap::new(), None),
+        };
+        let dummy_hash = bcrypt::hash(random_password(), bcrypt::DEFAULT_COST)?;
+
+        Ok(Self {
+            realm: config.realm.clone(),
+            static_users,
+            htpasswd_file: config.htpasswd_file.clone(),
+            state: RwLock::new(HtpasswdState {
+                users,
+                modified,
+                last_checked: Instant::now(),
+            }),
+            dummy_hash,
+        })
+    }
+
+    pub fn challenge(&self) -> HeaderValue {
+        let realm = self.realm.replace('"', "");
+        HeaderValue::from_str(&format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm))
+            .unwrap_or_else(|_| HeaderValue::from_static("Basic"))
+    }
+
+    async fn load_file(path: &PathBuf) -> Result<(HashMap<String, String>, Option<SystemTime>), AuthError> {
+        let modified = tokio::fs::metadata(path).await?.modified().ok();
+        let contents = tokio::fs::read_to_string(path).await?;
+        Ok((parse_htpasswd(&contents), modified))
+    }
+
+    async fn reload_if_changed(&self) {
+        let Some(path) = &self.htpasswd_file else {
+            return;
+        };
+
+        if self.state.read().await.last_checked.elapsed() < RELOAD_CHECK_INTERVAL {
+            return;
+        }
+
+        let mut state = self.state.write().await;
+        state.last_checked = Instant::now();
+
+        let modified = match tokio::fs::metadata(path).await.and_then(|m| m.modified()) {
+            Ok(modified) => Some(modified),
+            Err(e) => {
+                // Keep serving the last good copy
+                log::warn!("Cannot stat htpasswd file {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        if modified == state.modified {
+            return;
+        }
+
+        match Self::load_file(path).await {
+            Ok((users, modified)) => {
+                log::info!("Reloaded htpasswd file {} ({} users)", path.display(), users.len());
+                state.users = users;
+                state.modified = modified;
+            }
+            Err(e) => log::warn!("Failed to reload htpasswd file {}: {}", path.display(), e),
+        }
+    }
+
+    async fn password_hash(&self, username: &str) -> Option<String> {
+        if let Some(hash) = self.static_users.get(username) {
+            return Some(hash.clone());
+        }
+        self.state.read().await.users.get(username).cloned()
+    }
+}
+
+#[async_trait]
+impl Authenticator for BasicAuthenticator {
+    // `token` is the base64 credentials part of an `Authorization: Basic` header
+    async fn validate_token(&self, token: &str) -> Result<Claims, AuthError> {
+        let decoded = BASE64.decode(token.trim()).map_err(|_| AuthError::InvalidToken)?;
+        let decoded = String::from_utf8(decoded).map_err(|_| AuthError::InvalidToken)?;
+        let (username, password) = decoded.split_once(':').ok_or(AuthError::InvalidToken)?;
+
+        self.reload_if_changed().await;
+
+        let (hash, known) = match self.password_hash(username).await {
+            Some(hash) => (hash, true),
+            None => (self.dummy_hash.clone(), false),
+        };
+        let password = password.to_string();
+
+        // Password hashing is deliberately slow, keep it off the async workers
+        let valid = tokio::task::spawn_blocking(move || verify_password(&password, &hash))
+            .await
+            .unwrap_or(false);
+
+        if !valid || !known {
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as usize;
+
+        Ok(Claims {
+            sub: username.to_string(),
+            exp: now + CLAIMS_TTL,
+            iat: now,
+            iss: "gateway-basic".into(),
+            aud: self.realm.clone(),
+            scope: String::new(),
+            extra: HashMap::new(),
+        })
+    }
+}
+
+fn parse_htpasswd(contents: &str) -> HashMap<String, String> {
+    contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(user, hash)| (user.to_string(), hash.to_string()))
+        .collect()
+}
+
+fn random_password() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    if hash.starts_with("$2y$") || hash.starts_with("$2a$") || hash.starts_with("$2b$") {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    } else if hash.starts_with("$argon2") {
+        PasswordHash::new(hash)
+            .map(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+            .unwrap_or(false)
+    } else {
+        // MD5-crypt, SHA1 and plaintext htpasswd entries are not accepted
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::config::BasicUser;
+
+    fn credentials(user: &str, password: &str) -> String {
+        BASE64.encode(format!("{}:{}", user, password))
+    }
+
+    #[tokio::test]
+    async fn test_static_bcrypt_user() {
+        let config = BasicAuthConfig {
+            realm: "Admin".into(),
+            htpasswd_file: None,
+            users: vec![BasicUser {
+                username: "ops".into(),
+                password_hash: bcrypt::hash("hunter2", 4).unwrap(),
+            }],
+        };
+        let auth = BasicAuthenticator::new(&config).unwrap();
+
+        let claims = auth.validate_token(&credentials("ops", "hunter2")).await.unwrap();
+        assert_eq!(claims.sub, "ops");
+        assert!(claims.exp > claims.iat);
+        assert!(auth.validate_token(&credentials("ops", "wrong")).await.is_err());
+        assert!(auth.validate_token(&credentials("nobody", "hunter2")).await.is_err());
+        assert_eq!(auth.challenge(), "Basic realm=\"Admin\", charset=\"UTF-8\"");
+    }
+
+    #[test]
+    fn test_parse_htpasswd() {
+        let users = parse_htpasswd("# comment\nalice:$2y$05$abc\n\nbob:$argon2id$v=19$xyz\n");
+        assert_eq!(users.len(), 2);
+        assert_eq!(users["alice"], "$2y$05$abc");
+        assert!(!verify_password("secret", "plaintext"));
+    }
+}
@@ -0,0 +1,299 @@
+// src/main.rs
+use std::{net::SocketAddr, path::Path, sync::Arc, time::Duration};
+use hyper::{Body, Request, Server, StatusCode};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use tokio::signal;
+use crate::{
+    config::GatewayConfig,
+    logging::elk::ElkLogger,
+    models::ApiResponse,
+    routing::{matcher::RouteMatcher, proxy::ProxyHandler},
+    services::{admin::AdminApi, gateway::GatewayService, healthcheck::HealthCheckService, cache::CacheService, coalescing::RequestCoalescer, idempotency::IdempotencyStore},
+    utils::error::ApiError,
+    rate_limiting::{hybrid::HybridRateLimiter, overrides::{Cidr, OverrideStore}, policy::RateLimitPolicies, quota::{QuotaManager, QuotaPolicies}, redis_store::RedisRateLimiter},
+    auth::{jwt::JwtValidator, oauth::OAuthIntrospector},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Load configuration
+    let config = load_config().await?;
+    
+    // Initialize logging
+    ElkLogger::new(
+        &config.observability.logging.logstash_url,
+        &config.server.name,
+        &config.server.env
+    )?.init_logging()?;
+
+    // Initialize rate limiter, in-process only when no Redis is configured. Redis is
+    // connected on first use, so an outage at startup is handled like one later on.
+    let rate_limiter = if config.rate_limiting.redis_url.is_empty() {
+        log::warn!("No Redis configured for rate limiting, limits apply per instance");
+        HybridRateLimiter::local()
+    } else {
+        let redis = RedisRateLimiter::new(&config.rate_limiting.redis_url)
+            .map_err(|e| ApiError::ConfigError(format!("Invalid Redis URL: {}", e)))?;
+        HybridRateLimiter::new(redis)
+    };
+
+    let rate_limit_policies = RateLimitPolicies::from_file("config/rate_limits.yaml")
+        .map_err(|e| ApiError::ConfigError(e.to_string()))?;
+
+    // Idempotency keys live in the same Redis as the shared cache tier; serving
+    // such routes without it would process retried requests twice
+    let idempotency = rate_limiter.redis().map(|redis| Arc::new(IdempotencyStore::new(redis.connection())));
+    if let Some(route) = config.routing.routes.iter().find(|route| route.idempotency.is_some()).filter(|_| idempotency.is_none()) {
+        return Err(ApiError::ConfigError(format!("Route {} uses idempotency keys, which need Redis", route.path)).into());
+    }
+
+    // Build route matcher
+    let default_max_body_bytes = config.routing.default_max_body_bytes;
+    let route_matcher = RouteMatcher::new(config.routing.routes)
+        .map_err(|e| ApiError::ConfigError(format!("Invalid route configuration: {}", e)))?;
+
+    // Initialize auth components
+    let jwt_validator = JwtValidator::new(
+        config.auth.jwk_url.parse()?,
+        config.auth.issuer.clone(),
+        config.auth.audience.clone(),
+    );
+    
+    let oauth_introspector = OAuthIntrospector::new(
+        config.auth.introspection_url.parse()?,
+        config.auth.client_id.clone(),
+        config.auth.client_secret.clone(),
+    );
+
+    // Quota plans live in the shared Redis store; quotas.yaml seeds an empty one.
+    // Without Redis, consumers would silently go unmetered.
+    let quotas = match rate_limiter.redis() {
+        _ if !Path::new("config/quotas.yaml").exists() => None,
+        Some(redis) => {
+            let policies = QuotaPolicies::from_file("config/quotas.yaml")
+                .map_err(|e| ApiError::ConfigError(e.to_string()))?;
+            let quotas = Arc::new(QuotaManager::new(policies, redis.connection()));
+            tokio::spawn(quotas.clone().keep_synced());
+            Some(quotas)
+        }
+        None => return Err(ApiError::ConfigError("Quota plans are configured, which need Redis".into()).into()),
+    };
+
+    // Response cache, sharing the rate limiter's Redis connection as its second tier
+    let cache_config = &config.routing.cache;
+    let cache_service = cache_config.enabled.then(|| {
+        let mut cache = CacheService::new(
+            cache_config.max_entries,
+            Duration::from_secs(cache_config.ttl_seconds),
+            rate_limiter.redis().map(|redis| redis.connection()),
+        )
+        .with_max_object_bytes(cache_config.max_object_bytes)
+        .with_shared_value_limits(cache_config.compress_min_bytes, cache_config.max_shared_value_bytes)
+        .with_excluded_statuses(cache_config.excluded_statuses.clone())
+        .with_key_strategy(cache_config.cache_key_strategy.clone(), cache_config.ignored_query_params.clone());
+
+        if let Some(coalescing) = &cache_config.coalescing {
+            let mut coalescer = RequestCoalescer::new(Duration::from_millis(coalescing.timeout_ms));
+            match cache.redis() {
+                Some(redis) if coalescing.distributed => {
+                    coalescer = coalescer.with_redis(redis, Duration::from_secs(coalescing.lock_ttl_seconds));
+                }
+                None if coalescing.distributed => log::warn!("Cache coalescing is local only: no Redis configured"),
+                _ => {}
+            }
+            cache = cache.with_coalescing(coalescer);
+        }
+        Arc::new(cache)
+    });
+
+    let trusted_proxies = config.server.trusted_proxies.iter()
+        .map(|cidr| cidr.parse::<Cidr>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ApiError::ConfigError(format!("Invalid trusted proxy: {}", e)))?;
+
+    // Rate limit overrides are shared through Redis; edits on any instance reach this one
+    let overrides_conn = rate_limiter.redis().map(|redis| redis.connection());
+
+    // Create services
+    let mut gateway = GatewayService::new(
+        route_matcher,
+        rate_limiter,
+        jwt_validator,
+        oauth_introspector,
+    )?
+    .with_rate_limit_policies(rate_limit_policies)?
+    .with_trusted_proxies(trusted_proxies)
+    .with_default_max_body_bytes(default_max_body_bytes);
+
+    let overrides = overrides_conn.map(|conn| Arc::new(OverrideStore::new(gateway.rate_limit_policies(), conn)));
+    if let Some(overrides) = &overrides {
+        let client = redis::Client::open(config.rate_limiting.redis_url.as_str())
+            .map_err(|e| ApiError::ConfigError(format!("Invalid Redis URL: {}", e)))?;
+        tokio::spawn(overrides.clone().listen_for_changes(client));
+    }
+
+    if let Some(admin_config) = &config.admin {
+        let mut admin = AdminApi::new(admin_config).with_rate_limit_policies(gateway.rate_limit_policies());
+        if let Some(overrides) = overrides {
+            admin = admin.with_rate_limit_overrides(overrides);
+        }
+        if let Some(quotas) = &quotas {
+            admin = admin.with_quotas(quotas.clone());
+        }
+        if let Some(cache_service) = &cache_service {
+            admin = admin.with_cache(cache_service.clone());
+        }
+        gateway = gateway.with_admin(admin);
+    }
+    if let Some(quotas) = quotas {
+        gateway = gateway.with_quotas(quotas);
+    }
+    if let Some(idempotency) = idempotency {
+        gateway = gateway.with_idempotency(idempotency);
+    }
+    if let Some(cache_service) = cache_service {
+        // Keys rewritten or purged on any instance drop out of this one's local tier
+        if !config.rate_limiting.redis_url.is_empty() {
+            let client = redis::Client::open(config.rate_limiting.redis_url.as_str())
+                .map_err(|e| ApiError::ConfigError(format!("Invalid Redis URL: {}", e)))?;
+            tokio::spawn(cache_service.clone().listen_for_invalidations(client));
+        }
+        gateway = gateway.with_cache(cache_service)?;
+    }
+    let gateway = Arc::new(gateway);
+
+    // Drop in-process rate limit buckets that have refilled completely
+    let policies = gateway.rate_limit_policies();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            policies.evict_idle_local();
+        }
+    });
+
+    let health_check = Arc::new(HealthCheckService::new());
+
+    // Configure server
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.server.port));
+    let make_svc = make_service_fn(move |conn: &AddrStream| {
+        let gateway = gateway.clone();
+        let health_check = health_check.clone();
+        let remote_addr = conn.remote_addr();
+
+        async move {
+            Ok::<_, ApiError>(service_fn(move |req: Request<Body>| {
+                let gateway = gateway.clone();
+                let health_check = health_check.clone();
+
+                async move {
+                    // Handle health checks separately
+                    if req.uri().path() == "/health" {
+                        return Ok(health_check.health_endpoint());
+                    }
+
+                    // Process request
+                    let start_time = Instant::now();
+                    health_check.increment_requests();
+                    
+                    // Caching happens inside the gateway, after authentication
+                    let result = gateway.handle_request(req, remote_addr).await;
+
+                    // Record latency
+                    let latency = start_time.elapsed();
+                    metrics::histogram!("request_latency", latency);
+
+                    if !result.status.is_success() {
+                        health_check.increment_errors();
+                    }
+
+                    Ok(result)
+                }
+            }))
+        }
+    });
+
+    // Start server
+    let server = Server::bind(&addr)
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown_signal());
+
+    log::info!("Gateway running on {}", addr);
+    server.await?;
+
+    Ok(())
+}
+
+async fn load_config() -> Result<GatewayConfig, ApiError> {
+    let mut settings = config::Config::default();
+    settings
+        .merge(config::File::with_name("config/gateway"))
+        .map_err(|e| ApiError::ConfigError(e.to_string()))?
+        .merge(config::Environment::with_prefix("GATEWAY"))
+        .map_err(|e| ApiError::ConfigError(e.to_string()))?;
+
+    settings.try_into()
+        .map_err(|e| ApiError::ConfigError(e.to_string()))
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    log::info!("Shutting down gracefully...");
+}
+
+// Integration tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{Body, Request};
+    use wiremock::{MockServer, Mock, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_health_endpoint() {
+        let health = HealthCheckService::new();
+        let response = health.health_endpoint();
+        assert_eq!(response.status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_request_flow() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let config = GatewayConfig::test_config(mock_server.uri());
+        let gateway = setup_gateway(config).await;
+
+        let request = Request::builder()
+            .uri("/test")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = gateway.handle_request(request, "127.0.0.1:40000".parse().unwrap()).await;
+        assert_eq!(response.status, StatusCode::OK);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,759 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::time::Duration;
+use validator::Validate;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct GatewayConfig {
+    #[validate]
+    pub server: ServerConfig,
+    #[validate]
+    pub routing: RoutingConfig,
+    #[validate]
+    pub security: SecurityConfig,
+    #[validate]
+    pub observability: ObservabilityConfig,
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminConfig {
+    #[serde(default = "default_admin_prefix")]
+    pub prefix: String,
+    // SHA-256 hex digests of the bearer tokens accepted on admin endpoints
+    #[serde(default)]
+    pub tokens_sha256: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ServerConfig {
+    #[validate(range(min = 1, max = 65535))]
+    pub port: u16,
+    pub workers: Option<usize>,
+    pub max_connections: Option<u32>,
+    pub timeout: Duration,
+    // CIDRs of load balancers whose X-Forwarded-For is believed; clients
+    // connecting directly are identified by their socket address
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RoutingConfig {
+    #[validate]
+    pub routes: Vec<RouteConfig>,
+    pub default_backend: Option<String>,
+    pub cache: CacheConfig,
+    // Body limit of routes whose `limits` policy sets none, and of admin requests
+    #[validate(range(min = 1))]
+    #[serde(default = "default_max_body_bytes")]
+    pub default_max_body_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RouteConfig {
+    #[validate(length(min = 1))]
+    pub path: String,
+    #[validate(length(min = 1))]
+    pub backend: String,
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[validate]
+    pub rate_limiting: Option<RateLimitConfig>,
+    // Name of a policy from rate_limits.yaml
+    #[serde(default)]
+    pub rate_limit: Option<String>,
+    #[validate]
+    #[serde(deserialize_with = "deserialize_auth")]
+    pub authentication: AuthConfig,
+    #[validate]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    #[serde(default)]
+    pub signature_verification: Option<SignatureVerificationConfig>,
+    #[validate]
+    #[serde(default)]
+    pub concurrency: Option<ConcurrencyConfig>,
+    #[validate]
+    #[serde(default)]
+    pub adaptive_concurrency: Option<AdaptiveConcurrencyConfig>,
+    // Units of the rate limit one request consumes; 1 when unset
+    #[serde(default)]
+    pub rate_limit_cost: Option<RequestCost>,
+    #[serde(default)]
+    pub cache: Option<RouteCacheConfig>,
+    #[validate]
+    #[serde(default)]
+    pub idempotency: Option<IdempotencyConfig>,
+    #[validate]
+    #[serde(default)]
+    pub compression: Option<CompressionConfig>,
+    #[validate]
+    #[serde(default)]
+    pub limits: Option<RequestLimitsConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RateLimitConfig {
+    #[validate(range(min = 1))]
+    pub requests: u32,
+    #[validate(range(min = 1))]
+    pub per_seconds: u32,
+    pub algorithm: RateLimitAlgorithm,
+    pub scope: RateLimitScope,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    #[validate(range(min = 1))]
+    pub ttl_seconds: u64,
+    pub excluded_statuses: Vec<u16>,
+    pub cache_key_strategy: CacheKeyStrategy,
+    // Query parameters left out of cache keys; a trailing `*` matches a prefix
+    #[serde(default = "default_ignored_query_params")]
+    pub ignored_query_params: Vec<String>,
+    #[serde(default = "default_cache_entries")]
+    pub max_entries: u64,
+    // Larger responses are passed through without being stored
+    #[serde(default = "default_cache_object_bytes")]
+    pub max_object_bytes: usize,
+    // Values shared through Redis are zstd-compressed from this size on
+    #[serde(default = "default_cache_compress_min_bytes")]
+    pub compress_min_bytes: usize,
+    // Larger values, after compression, are kept out of Redis
+    #[serde(default = "default_cache_shared_value_bytes")]
+    pub max_shared_value_bytes: usize,
+    // Concurrent misses for a key wait for one upstream fetch
+    pub coalescing: Option<CoalescingConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CoalescingConfig {
+    // After this, waiting requests go to the backend themselves
+    #[serde(default = "default_coalescing_timeout_ms")]
+    pub timeout_ms: u64,
+    // Also coalesce across gateway instances with a Redis lock per key
+    #[serde(default)]
+    pub distributed: bool,
+    // Locks of crashed instances expire after this
+    #[serde(default = "default_lease_ttl")]
+    pub lock_ttl_seconds: u64,
+}
+
+// Responses are stored as the backend's Cache-Control allows
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct RouteCacheConfig {
+    // Freshness for responses without Cache-Control max-age or Expires
+    pub ttl_seconds: Option<u64>,
+    // Stale windows for responses without stale-while-revalidate / stale-if-error
+    pub stale_while_revalidate_seconds: Option<u64>,
+    pub stale_if_error_seconds: Option<u64>,
+    // Overrides the global `cache_key_strategy`
+    pub key: Option<CacheKeyStrategy>,
+    // Let authenticated callers share entries; only for responses that do
+    // not depend on who is asking
+    #[serde(default)]
+    pub shared: bool,
+    #[serde(default)]
+    pub tier: CacheTier,
+    // Report HIT/MISS/STALE/BYPASS in an X-Cache response header
+    #[serde(default)]
+    pub x_cache_header: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SecurityConfig {
+    #[validate]
+    pub cors: CorsConfig,
+    #[validate]
+    pub headers: SecurityHeaders,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ObservabilityConfig {
+    #[validate]
+    pub logging: LoggingConfig,
+    #[validate]
+    pub metrics: MetricsConfig,
+    #[validate]
+    pub tracing: TracingConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub required: bool,
+    // Overrides `required` when set
+    #[serde(default)]
+    pub mode: Option<AuthMode>,
+    // Methods tried in order; derived from the configured authenticators when empty
+    #[serde(default)]
+    pub chain: Vec<AuthMethod>,
+    #[serde(default)]
+    pub chain_policy: ChainPolicy,
+    pub jwt: Option<JwtConfig>,
+    pub oauth: Option<OAuthConfig>,
+    #[serde(default)]
+    pub oidc_login: Option<OidcLoginConfig>,
+    #[serde(default)]
+    pub basic: Option<BasicAuthConfig>,
+    #[serde(default)]
+    pub api_key: Option<ApiKeyConfig>,
+}
+
+impl AuthConfig {
+    pub fn effective_mode(&self) -> AuthMode {
+        match &self.mode {
+            Some(mode) => mode.clone(),
+            None if self.required => AuthMode::Required,
+            None => AuthMode::Disabled,
+        }
+    }
+
+    pub fn methods(&self) -> Vec<AuthMethod> {
+        if !self.chain.is_empty() {
+            return self.chain.clone();
+        }
+
+        let mut methods = Vec::new();
+        if self.jwt.is_some() {
+            methods.push(AuthMethod::Jwt);
+        }
+        if self.oauth.is_some() {
+            methods.push(AuthMethod::Oauth);
+        }
+        if self.basic.is_some() {
+            methods.push(AuthMethod::Basic);
+        }
+        if self.api_key.is_some() {
+            methods.push(AuthMethod::ApiKey);
+        }
+        methods
+    }
+}
+
+// Accepts both `auth: optional` and a full auth block
+fn deserialize_auth<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AuthConfig, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Mode(AuthMode),
+        Config(AuthConfig),
+    }
+
+    Ok(match Repr::deserialize(deserializer)? {
+        Repr::Mode(mode) => AuthConfig {
+            mode: Some(mode),
+            ..Default::default()
+        },
+        Repr::Config(config) => config,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ApiKeyConfig {
+    #[serde(default = "default_api_key_header")]
+    pub header: String,
+    #[serde(default)]
+    pub keys: Vec<ApiKeyEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyEntry {
+    pub consumer: String,
+    // Hex SHA-256 of the key
+    pub key_sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct BasicAuthConfig {
+    #[serde(default = "default_basic_realm")]
+    pub realm: String,
+    pub htpasswd_file: Option<PathBuf>,
+    #[serde(default)]
+    pub users: Vec<BasicUser>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasicUser {
+    pub username: String,
+    // bcrypt or argon2 PHC string, never a plaintext password
+    pub password_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct OidcLoginConfig {
+    #[validate(url)]
+    pub authorization_endpoint: String,
+    #[validate(url)]
+    pub token_endpoint: String,
+    #[validate(length(min = 1))]
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    // Absolute callback URL registered with the IdP; its path is served by the gateway
+    #[validate(url)]
+    pub redirect_uri: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    #[validate]
+    pub session: OidcSessionConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct OidcSessionConfig {
+    #[serde(default)]
+    pub store: SessionStoreKind,
+    #[serde(default = "default_session_cookie")]
+    pub cookie_name: String,
+    // Used to encrypt session and login-state cookies
+    #[validate(length(min = 32))]
+    pub encryption_key: String,
+    #[serde(default = "default_session_ttl")]
+    pub ttl_seconds: u64,
+    pub redis_url: Option<String>,
+    #[serde(default = "default_true")]
+    pub secure_cookie: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SignatureVerificationConfig {
+    #[validate(length(min = 1))]
+    pub secret: String,
+    #[serde(default)]
+    pub preset: SignaturePreset,
+    pub header: Option<String>,
+    pub prefix: Option<String>,
+    pub timestamp_header: Option<String>,
+    pub timestamp_key: Option<String>,
+    pub signature_key: Option<String>,
+    pub payload_prefix: Option<String>,
+    pub encoding: Option<SignatureEncoding>,
+    pub tolerance_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ConcurrencyConfig {
+    #[validate(range(min = 1))]
+    pub max_in_flight: u32,
+    #[serde(default)]
+    pub scope: ConcurrencyScope,
+    // Enforce the cap across all gateway instances with Redis leases
+    #[serde(default)]
+    pub distributed: bool,
+    // Requests allowed to wait for a slot; 0 rejects immediately
+    #[serde(default)]
+    pub max_queue: u32,
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+    // Leases of crashed instances expire after this; held leases are renewed
+    #[serde(default = "default_lease_ttl")]
+    pub lease_ttl_seconds: u64,
+}
+
+// Per-upstream limit that follows observed latency and errors
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct AdaptiveConcurrencyConfig {
+    #[serde(default)]
+    pub algorithm: AdaptiveAlgorithm,
+    #[serde(default = "default_adaptive_initial_limit")]
+    pub initial_limit: u32,
+    #[validate(range(min = 1))]
+    #[serde(default = "default_adaptive_min_limit")]
+    pub min_limit: u32,
+    #[serde(default = "default_adaptive_max_limit")]
+    pub max_limit: u32,
+    // Multiplicative decrease, applied at most once per RTT window
+    #[validate(range(min = 0.1, max = 0.99))]
+    #[serde(default = "default_backoff_ratio")]
+    pub backoff_ratio: f64,
+    // Share of errors and timeouts within an RTT window that triggers a backoff
+    #[validate(range(min = 0.0, max = 1.0))]
+    #[serde(default = "default_max_error_rate")]
+    pub max_error_rate: f64,
+    // AIMD treats slower responses as overload
+    #[serde(default = "default_latency_threshold_ms")]
+    pub latency_threshold_ms: u64,
+    // Weight of each new gradient sample
+    #[validate(range(min = 0.01, max = 1.0))]
+    #[serde(default = "default_smoothing")]
+    pub smoothing: f64,
+}
+
+// Retries carrying the same idempotency key get the first response again
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct IdempotencyConfig {
+    #[serde(default = "default_idempotency_header")]
+    pub header: String,
+    // Reject covered requests without a key instead of forwarding them unprotected
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default = "default_idempotency_methods")]
+    pub methods: Vec<String>,
+    // How long a stored response is replayed
+    #[validate(range(min = 1))]
+    #[serde(default = "default_idempotency_window")]
+    pub window_seconds: u64,
+    // Keys held by a crashed instance are freed after this
+    #[serde(default = "default_lease_ttl")]
+    pub lock_ttl_seconds: u64,
+    // Larger responses are not stored; retries of their keys get 409 until the window ends
+    #[serde(default = "default_cache_object_bytes")]
+    pub max_response_bytes: usize,
+}
+
+// Responses are compressed for clients whose Accept-Encoding allows it
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CompressionConfig {
+    // Preferred first when a client accepts several equally
+    #[serde(default = "default_compression_encodings")]
+    pub encodings: Vec<ContentEncoding>,
+    #[serde(default = "default_compression_min_bytes")]
+    pub min_bytes: usize,
+    // Larger bodies are passed through rather than buffered
+    #[serde(default = "default_cache_object_bytes")]
+    pub max_bytes: usize,
+    // A trailing `*` matches any subtype, e.g. `text/*`
+    #[serde(default = "default_compressible_types")]
+    pub content_types: Vec<String>,
+    // Clamped to each algorithm's range; its usual default when unset
+    #[serde(default)]
+    pub level: Option<i32>,
+    // Inflate `Content-Encoding: gzip` uploads for backends that cannot
+    #[serde(default)]
+    pub decompress_requests: bool,
+    #[validate(range(min = 1))]
+    #[serde(default = "default_decompressed_request_bytes")]
+    pub max_decompressed_bytes: usize,
+}
+
+// Requests over any of these are turned away before their body is read
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct RequestLimitsConfig {
+    #[validate(range(min = 1))]
+    pub max_body_bytes: Option<usize>,
+    // Names and values of all request headers together
+    #[validate(range(min = 1))]
+    pub max_header_bytes: Option<usize>,
+    // Path and query string
+    #[validate(range(min = 1))]
+    pub max_url_length: Option<usize>,
+}
+
+// Enum definitions
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitAlgorithm {
+    #[default]
+    TokenBucket,
+    FixedWindow,
+    // Approximated from the current and previous window counters
+    #[serde(alias = "sliding_window_counter")]
+    SlidingWindow,
+    // Exact, at the cost of one sorted-set entry per request
+    SlidingWindowLog,
+    Gcra,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RateLimitScope {
+    Global,
+    PerIp,
+    PerUser,
+}
+
+// `full_url`, `path_only`, or anything else as a custom key template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum CacheKeyStrategy {
+    FullUrl,
+    PathOnly,
+    Custom(String),
+}
+
+impl From<String> for CacheKeyStrategy {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "full_url" => CacheKeyStrategy::FullUrl,
+            "path_only" => CacheKeyStrategy::PathOnly,
+            _ => CacheKeyStrategy::Custom(s),
+        }
+    }
+}
+
+impl From<CacheKeyStrategy> for String {
+    fn from(strategy: CacheKeyStrategy) -> Self {
+        match strategy {
+            CacheKeyStrategy::FullUrl => "full_url".into(),
+            CacheKeyStrategy::PathOnly => "path_only".into(),
+            CacheKeyStrategy::Custom(template) => template,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignaturePreset {
+    Stripe,
+    Github,
+    Slack,
+    #[default]
+    Custom,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureEncoding {
+    Hex,
+    Base64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    Required,
+    Optional,
+    Disabled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    Jwt,
+    Oauth,
+    Basic,
+    ApiKey,
+    Anonymous,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainPolicy {
+    #[default]
+    FirstSuccess,
+    AllMustPass,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStoreKind {
+    #[default]
+    Cookie,
+    Redis,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdaptiveAlgorithm {
+    // Additive increase, multiplicative decrease
+    #[default]
+    Aimd,
+    // Scales the limit by the ratio of baseline to current RTT
+    Gradient,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestCost {
+    Fixed(u32),
+    // One unit per started `bytes_per_unit` of request body
+    BodySize {
+        bytes_per_unit: u64,
+        #[serde(default = "default_min_cost")]
+        min: u32,
+        max: Option<u32>,
+    },
+    // `upfront` is charged on admission, the rest of the cost reported by the
+    // backend in `header` once its response arrives, capped at `max`
+    ResponseHeader {
+        header: String,
+        #[serde(default = "default_min_cost")]
+        upfront: u32,
+        #[serde(default = "default_max_reported_cost")]
+        max: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentEncoding {
+    Gzip,
+    #[serde(rename = "br")]
+    Brotli,
+    Zstd,
+}
+
+impl ContentEncoding {
+    // Token used in Accept-Encoding and Content-Encoding
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Zstd => "zstd",
+        }
+    }
+}
+
+// Where a route's responses are cached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheTier {
+    // In-process, backed by Redis shared with the other instances
+    #[default]
+    Both,
+    // In-process only, e.g. for cheap responses not worth the Redis memory
+    Local,
+    // Redis only, so every instance always sees the same copy
+    Redis,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConcurrencyScope {
+    #[default]
+    Route,
+    // Shared by every route pointing at the same backend
+    Upstream,
+}
+
+fn default_api_key_header() -> String {
+    "X-API-Key".into()
+}
+
+fn default_basic_realm() -> String {
+    "Restricted".into()
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec!["openid".into(), "profile".into(), "email".into()]
+}
+
+fn default_session_cookie() -> String {
+    "gateway_session".into()
+}
+
+fn default_session_ttl() -> u64 {
+    8 * 3600
+}
+
+fn default_queue_timeout_ms() -> u64 {
+    1_000
+}
+
+fn default_coalescing_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_lease_ttl() -> u64 {
+    60
+}
+
+fn default_adaptive_initial_limit() -> u32 {
+    20
+}
+
+fn default_adaptive_min_limit() -> u32 {
+    1
+}
+
+fn default_max_body_bytes() -> usize {
+    crate::services::request_limits::DEFAULT_MAX_BODY_BYTES
+}
+
+fn default_adaptive_max_limit() -> u32 {
+    1_000
+}
+
+fn default_backoff_ratio() -> f64 {
+    0.9
+}
+
+fn default_max_error_rate() -> f64 {
+    0.1
+}
+
+fn default_latency_threshold_ms() -> u64 {
+    1_000
+}
+
+fn default_smoothing() -> f64 {
+    0.2
+}
+
+fn default_ignored_query_params() -> Vec<String> {
+    ["utm_*", "gclid", "fbclid", "mc_cid", "mc_eid"].map(String::from).to_vec()
+}
+
+fn default_cache_entries() -> u64 {
+    10_000
+}
+
+fn default_cache_object_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_cache_compress_min_bytes() -> usize {
+    1024
+}
+
+fn default_cache_shared_value_bytes() -> usize {
+    512 * 1024
+}
+
+fn default_compression_encodings() -> Vec<ContentEncoding> {
+    vec![ContentEncoding::Brotli, ContentEncoding::Zstd, ContentEncoding::Gzip]
+}
+
+fn default_compression_min_bytes() -> usize {
+    1024
+}
+
+fn default_compressible_types() -> Vec<String> {
+    ["text/*", "application/json", "application/problem+json", "application/javascript", "application/xml", "image/svg+xml"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_decompressed_request_bytes() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_idempotency_header() -> String {
+    "Idempotency-Key".into()
+}
+
+fn default_idempotency_methods() -> Vec<String> {
+    vec!["POST".into(), "PATCH".into()]
+}
+
+fn default_idempotency_window() -> u64 {
+    24 * 3600
+}
+
+fn default_min_cost() -> u32 {
+    1
+}
+
+fn default_max_reported_cost() -> u32 {
+    100
+}
+
+fn default_admin_prefix() -> String {
+    "/gateway/admin".into()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// Validation implementations
+impl Validate for GatewayConfig {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        self.server.validate()?;
+        self.routing.validate()?;
+        self.security.validate()?;
+        self.observability.validate()
+    }
+}
+
+// Additional config structs and validation implementations...
\ No newline at end of file
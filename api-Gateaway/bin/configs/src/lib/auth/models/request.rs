@@ -0,0 +1,125 @@
+use hyper::{HeaderMap, Method, Uri, body::Bytes};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
+use jsonwebtoken::errors::Error as JwtError;
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use crate::auth::jwt::Claims;
+use crate::rate_limiting::overrides::Cidr;
+
+#[derive(Debug, Clone)]
+pub struct ApiRequest {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    // Client address, see `client_addr`
+    pub remote_addr: Option<SocketAddr>,
+    pub received_at: Instant,
+    pub path_params: HashMap<String, String>,
+    pub query_params: HashMap<String, String>,
+    // Set once the route's authenticator has accepted the request
+    pub identity: Option<Claims>,
+}
+
+impl ApiRequest {
+    pub fn jwt_claims(&self, secret: &str) -> Result<Claims, JwtError> {
+        let token = self.headers.get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.strip_prefix("Bearer "))
+            .unwrap_or("");
+        
+        let key = DecodingKey::from_secret(secret.as_ref());
+        decode::<Claims>(token, &key, &Validation::default()).map(|data| data.claims)
+    }
+
+    pub fn client_ip(&self) -> Option<String> {
+        self.remote_addr.map(|a| a.ip().to_string())
+    }
+
+    pub fn content_type(&self) -> Option<String> {
+        self.headers.get("Content-Type")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string())
+    }
+}
+
+#[cfg(test)]
+impl ApiRequest {
+    // Bare request for unit tests; set headers, body or identity afterwards as needed
+    pub fn for_test(method: Method, uri: &str) -> Self {
+        Self {
+            method,
+            uri: uri.parse().expect("test URI is valid"),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            remote_addr: None,
+            received_at: Instant::now(),
+            path_params: HashMap::new(),
+            query_params: HashMap::new(),
+            identity: None,
+        }
+    }
+}
+
+// Like nginx's realip module: X-Forwarded-For (or X-Real-IP) is only believed
+// for hops added by trusted proxies, walking the chain back from the socket peer.
+// The port stays the peer's.
+pub fn client_addr(peer: SocketAddr, headers: &HeaderMap, trusted_proxies: &[Cidr]) -> SocketAddr {
+    let trusted = |ip: IpAddr| trusted_proxies.iter().any(|cidr| cidr.contains(ip));
+    if !trusted(peer.ip()) {
+        return peer;
+    }
+
+    let mut hops: Vec<&str> = headers.get_all("X-Forwarded-For").iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .collect();
+    if hops.is_empty() {
+        hops.extend(headers.get("X-Real-IP").and_then(|h| h.to_str().ok()));
+    }
+
+    let mut client = peer.ip();
+    for hop in hops.iter().rev() {
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+        if !trusted(client) {
+            break;
+        }
+    }
+    SocketAddr::new(client, peer.port())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestInfo {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub client_ip: Option<String>,
+    pub timestamp: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_addr_trusts_only_configured_proxies() {
+        let trusted: Vec<Cidr> = vec!["10.0.0.0/8".parse().unwrap()];
+        let headers: HeaderMap = [("x-forwarded-for".parse().unwrap(), "6.6.6.6, 8.8.8.8, 10.1.0.1".parse().unwrap())]
+            .into_iter().collect();
+        let ip = |peer: &str| client_addr(peer.parse().unwrap(), &headers, &trusted).ip().to_string();
+
+        // Spoofed hops in front of the first untrusted address are ignored
+        assert_eq!(ip("10.0.0.2:4000"), "8.8.8.8");
+        // Clients connecting directly cannot pick their address
+        assert_eq!(ip("9.9.9.9:4000"), "9.9.9.9");
+
+        let headers = HeaderMap::new();
+        assert_eq!(client_addr("10.0.0.2:4000".parse().unwrap(), &headers, &trusted).ip().to_string(), "10.0.0.2");
+    }
+}
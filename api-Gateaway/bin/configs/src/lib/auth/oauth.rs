@@ -0,0 +1,351 @@
+use serde::{Deserialize, Serialize};
+use reqwest::Url;
+use thiserror::Error;
+use async_trait::async_trait;
+use moka::future::Cache;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::auth::jwt::Claims;
+
+const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+// Only `active` is mandatory per RFC 7662, everything else may be omitted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub sub: Option<String>,
+    #[serde(default)]
+    pub token_type: Option<String>,
+    #[serde(default)]
+    pub exp: Option<u64>,
+    #[serde(default)]
+    pub iat: Option<u64>,
+    #[serde(default)]
+    pub iss: Option<String>,
+}
+
+impl IntrospectionResponse {
+    pub fn scopes(&self) -> impl Iterator<Item = &str> {
+        self.scope.as_deref().unwrap_or_default().split_whitespace()
+    }
+
+    pub fn into_claims(self) -> Claims {
+        let extra = [("client_id", &self.client_id), ("username", &self.username), ("token_type", &self.token_type)]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_string(), serde_json::Value::String(value.clone()?))))
+            .collect();
+
+        Claims {
+            sub: self.sub.or(self.username).or(self.client_id).unwrap_or_default(),
+            exp: self.exp.unwrap_or_default() as usize,
+            iat: self.iat.unwrap_or_default() as usize,
+            iss: self.iss.unwrap_or_default(),
+            aud: String::new(),
+            scope: self.scope.unwrap_or_default(),
+            extra,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OAuthError {
+    #[error("Invalid token")]
+    InvalidToken,
+    #[error("Introspection failed: {0}")]
+    IntrospectionError(String),
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("Client assertion error: {0}")]
+    ClientAssertionError(#[from] jsonwebtoken::errors::Error),
+}
+
+pub enum ClientAuth {
+    ClientSecretPost(String),
+    ClientSecretBasic(String),
+    PrivateKeyJwt {
+        key: EncodingKey,
+        algorithm: Algorithm,
+        key_id: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct ClientAssertionClaims<'a> {
+    iss: &'a str,
+    sub: &'a str,
+    aud: &'a str,
+    jti: String,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Clone)]
+struct CachedIntrospection {
+    response: IntrospectionResponse,
+    expires_at: Instant,
+}
+
+pub struct OAuthIntrospector {
+    client: reqwest::Client,
+    introspection_url: Url,
+    client_id: String,
+    client_auth: ClientAuth,
+    max_cache_ttl: Duration,
+    negative_cache_ttl: Duration,
+    cache: Cache<String, CachedIntrospection>,
+}
+
+impl OAuthIntrospector {
+    pub fn new(
+        introspection_url: Url,
+        client_id: String,
+        client_secret: String,
+    ) -> Self {
+        let max_cache_ttl = Duration::from_secs(300);
+        Self {
+            client: reqwest::Client::new(),
+            introspection_url,
+            client_id,
+            client_auth: ClientAuth::ClientSecretPost(client_secret),
+            max_cache_ttl,
+            negative_cache_ttl: Duration::from_secs(10),
+            cache: Self::build_cache(max_cache_ttl),
+        }
+    }
+
+    pub fn with_client_auth(mut self, client_auth: ClientAuth) -> Self {
+        self.client_auth = client_auth;
+        self
+    }
+
+    pub fn with_max_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.max_cache_ttl = ttl;
+        self.cache = Self::build_cache(ttl);
+        self
+    }
+
+    pub fn with_negative_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_cache_ttl = ttl;
+        self
+    }
+
+    fn build_cache(ttl: Duration) -> Cache<String, CachedIntrospection> {
+        Cache::builder()
+            .max_capacity(100_000)
+            .time_to_live(ttl)
+            .build()
+    }
+
+    // Raw bearer tokens never end up as cache keys
+    fn cache_key(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    fn cache_ttl(&self, introspection: &IntrospectionResponse) -> Duration {
+        if !introspection.active {
+            return self.negative_cache_ttl.min(self.max_cache_ttl);
+        }
+
+        match introspection.exp {
+            Some(exp) => Duration::from_secs(exp.saturating_sub(Self::now())).min(self.max_cache_ttl),
+            None => self.max_cache_ttl,
+        }
+    }
+
+    fn client_assertion(&self) -> Result<Option<String>, OAuthError> {
+        let ClientAuth::PrivateKeyJwt { key, algorithm, key_id } = &self.client_auth else {
+            return Ok(None);
+        };
+
+        let now = Self::now();
+        let claims = ClientAssertionClaims {
+            iss: &self.client_id,
+            sub: &self.client_id,
+            aud: self.introspection_url.as_str(),
+            jti: uuid::Uuid::new_v4().to_string(),
+            iat: now,
+            exp: now + 60,
+        };
+
+        let mut header = Header::new(*algorithm);
+        header.kid = key_id.clone();
+
+        Ok(Some(encode(&header, &claims, key)?))
+    }
+
+    pub async fn introspect_token(
+        &self,
+        token: &str,
+    ) -> Result<IntrospectionResponse, OAuthError> {
+        let key = Self::cache_key(token);
+        if let Some(cached) = self.cache.get(&key) {
+            if cached.expires_at > Instant::now() {
+                return if cached.response.active {
+                    Ok(cached.response)
+                } else {
+                    Err(OAuthError::InvalidToken)
+                };
+            }
+            self.cache.invalidate(&key).await;
+        }
+
+        let assertion = self.client_assertion()?;
+        let mut params = vec![("token", token)];
+        let mut request = self.client.post(self.introspection_url.clone());
+
+        match &self.client_auth {
+            ClientAuth::ClientSecretPost(secret) => {
+                params.push(("client_id", self.client_id.as_str()));
+                params.push(("client_secret", secret.as_str()));
+            }
+            ClientAuth::ClientSecretBasic(secret) => {
+                request = request.basic_auth(&self.client_id, Some(secret));
+            }
+            ClientAuth::PrivateKeyJwt { .. } => {
+                params.push(("client_id", self.client_id.as_str()));
+                params.push(("client_assertion_type", CLIENT_ASSERTION_TYPE));
+                params.push(("client_assertion", assertion.as_deref().unwrap_or_default()));
+            }
+        }
+
+        let response = request
+            .form(&params)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(OAuthError::IntrospectionError(
+                response.status().to_string(),
+            ));
+        }
+
+        let introspection: IntrospectionResponse = response.json().await?;
+
+        let ttl = self.cache_ttl(&introspection);
+        if !ttl.is_zero() {
+            self.cache.insert(key, CachedIntrospection {
+                response: introspection.clone(),
+                expires_at: Instant::now() + ttl,
+            }).await;
+        }
+
+        if introspection.active {
+            Ok(introspection)
+        } else {
+            Err(OAuthError::InvalidToken)
+        }
+    }
+}
+
+#[async_trait]
+pub trait TokenValidator {
+    async fn validate_token(&self, token: &str) -> Result<(), OAuthError>;
+}
+
+pub struct OAuthTokenValidator {
+    introspector: OAuthIntrospector,
+    required_scopes: Vec<String>,
+}
+
+impl OAuthTokenValidator {
+    pub fn new(introspector: OAuthIntrospector, required_scopes: Vec<String>) -> Self {
+        Self {
+            introspector,
+            required_scopes,
+        }
+    }
+}
+
+#[async_trait]
+impl TokenValidator for OAuthTokenValidator {
+    async fn validate_token(&self, token: &str) -> Result<(), OAuthError> {
+        let introspection = self.introspector.introspect_token(token).await?;
+
+        if !self.required_scopes.iter().all(|scope|
+            introspection.scopes().any(|s| s == scope)
+        ) {
+            return Err(OAuthError::InvalidToken);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::{Expectation, Server, all_of, matchers::*, responders::*};
+
+    fn introspector(uri: &str) -> OAuthIntrospector {
+        OAuthIntrospector::new(
+            uri.parse().unwrap(),
+            "gateway".into(),
+            "secret".into(),
+        )
+    }
+
+    #[test]
+    fn test_minimal_response_parses() {
+        let parsed: IntrospectionResponse = serde_json::from_str(r#"{"active": false}"#).unwrap();
+        assert!(!parsed.active);
+        assert!(parsed.scope.is_none());
+        assert!(parsed.username.is_none());
+    }
+
+    #[test]
+    fn test_cache_ttl_bounded_by_exp() {
+        let introspector = introspector("http://localhost/introspect");
+        let response = IntrospectionResponse {
+            active: true,
+            scope: None,
+            client_id: None,
+            username: None,
+            sub: None,
+            token_type: None,
+            exp: Some(OAuthIntrospector::now() + 30),
+            iat: None,
+            iss: None,
+        };
+
+        assert!(introspector.cache_ttl(&response) <= Duration::from_secs(30));
+        assert_ne!(OAuthIntrospector::cache_key("token"), "token");
+    }
+
+    #[tokio::test]
+    async fn test_inactive_token_is_negatively_cached() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method("POST"),
+                request::headers(contains(key("authorization"))),
+            ])
+            .times(1)
+            .respond_with(status_code(200).body(r#"{"active": false}"#)),
+        );
+
+        let introspector = introspector(&server.url_str("/introspect"))
+            .with_client_auth(ClientAuth::ClientSecretBasic("secret".into()));
+
+        for _ in 0..2 {
+            assert!(matches!(
+                introspector.introspect_token("revoked").await,
+                Err(OAuthError::InvalidToken)
+            ));
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hyper::header::{HeaderValue, ACCEPT, COOKIE, LOCATION, SET_COOKIE};
+use hyper::StatusCode;
+use rand::{rngs::OsRng, RngCore};
+use redis::AsyncCommands;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use crate::models::{ApiRequest, ApiResponse};
+use crate::models::config::{OidcLoginConfig, SessionStoreKind};
+
+const NONCE_LEN: usize = 12;
+const LOGIN_STATE_TTL: u64 = 600;
+// Access tokens are refreshed this many seconds before they expire
+const REFRESH_SKEW: u64 = 30;
+
+#[derive(Debug, Error)]
+pub enum OidcError {
+    #[error("Not authenticated")]
+    Unauthenticated,
+    #[error("Missing or invalid login state")]
+    InvalidState,
+    #[error("Authorization failed: {0}")]
+    AuthorizationError(String),
+    #[error("Token endpoint error: {0}")]
+    TokenError(String),
+    #[error("Session error: {0}")]
+    SessionError(String),
+    #[error("Invalid OIDC configuration: {0}")]
+    ConfigError(String),
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+    #[error("Redis error: {0}")]
+    RedisError(#[from] redis::RedisError),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcSession {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub id_token: Option<String>,
+    pub expires_at: u64,
+}
+
+impl OidcSession {
+    fn needs_refresh(&self) -> bool {
+        self.expires_at <= now() + REFRESH_SKEW
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingLogin {
+    state: String,
+    code_verifier: String,
+    return_to: String,
+    expires_at: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    id_token: Option<String>,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+pub enum OidcOutcome {
+    // The access token to forward upstream, plus a cookie to set if the session changed
+    Authenticated {
+        access_token: String,
+        set_cookie: Option<HeaderValue>,
+    },
+    Redirect(ApiResponse),
+}
+
+pub struct CookieCipher {
+    cipher: Aes256Gcm,
+}
+
+impl CookieCipher {
+    pub fn new(secret: &str) -> Self {
+        let key = Sha256::digest(secret.as_bytes());
+        Self {
+            cipher: Aes256Gcm::new(&key),
+        }
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<String, OidcError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            self.cipher
+                .encrypt(Nonce::from_slice(&nonce), plaintext)
+                .map_err(|_| OidcError::SessionError("encryption failed".into()))?,
+        );
+        Ok(URL_SAFE_NO_PAD.encode(sealed))
+    }
+
+    pub fn open(&self, value: &str) -> Option<Vec<u8>> {
+        let sealed = URL_SAFE_NO_PAD.decode(value).ok()?;
+        if sealed.len() <= NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+    }
+}
+
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn load(&self, cookie_value: &str) -> Result<Option<OidcSession>, OidcError>;
+    // Returns the cookie value that identifies the stored session
+    async fn save(&self, cookie_value: Option<&str>, session: &OidcSession) -> Result<String, OidcError>;
+}
+
+// Keeps the whole session inside the encrypted cookie, no server-side state
+pub struct CookieSessionStore {
+    cipher: CookieCipher,
+}
+
+#[async_trait]
+impl SessionStore for CookieSessionStore {
+    async fn load(&self, cookie_value: &str) -> Result<Option<OidcSession>, OidcError> {
+        Ok(self.cipher
+            .open(cookie_value)
+            .and_then(|plain| serde_json::from_slice(&plain).ok()))
+    }
+
+    async fn save(&self, _cookie_value: Option<&str>, session: &OidcSession) -> Result<String, OidcError> {
+        let plain = serde_json::to_vec(session)
+            .map_err(|e| OidcError::SessionError(e.to_string()))?;
+        self.cipher.seal(&plain)
+    }
+}
+
+// Keeps tokens in Redis; the cookie only carries an encrypted session id
+pub struct RedisSessionStore {
+    client: redis::Client,
+    cipher: CookieCipher,
+    ttl_seconds: u64,
+}
+
+impl RedisSessionStore {
+    fn redis_key(session_id: &str) -> String {
+        format!("oidc_session:{}", session_id)
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn load(&self, cookie_value: &str) -> Result<Option<OidcSession>, OidcError> {
+        let Some(session_id) = self.cipher.open(cookie_value).and_then(|id| String::from_utf8(id).ok()) else {
+            return Ok(None);
+        };
+
+        let mut conn = self.client.get_async_connection().await?;
+        let data: Option<String> = conn.get(Self::redis_key(&session_id)).await?;
+        Ok(data.and_then(|d| serde_json::from_str(&d).ok()))
+    }
+
+    async fn save(&self, cookie_value: Option<&str>, session: &OidcSession) -> Result<String, OidcError> {
+        let session_id = cookie_value
+            .and_then(|value| self.cipher.open(value))
+            .and_then(|id| String::from_utf8(id).ok())
+            .unwrap_or_else(random_token);
+
+        let data = serde_json::to_string(session)
+            .map_err(|e| OidcError::SessionError(e.to_string()))?;
+
+        let mut conn = self.client.get_async_connection().await?;
+        conn.set_ex(Self::redis_key(&session_id), data, self.ttl_seconds as usize).await?;
+
+        self.cipher.seal(session_id.as_bytes())
+    }
+}
+
+pub struct OidcLoginHandler {
+    client: reqwest::Client,
+    config: OidcLoginConfig,
+    callback_path: String,
+    cipher: CookieCipher,
+    store: Box<dyn SessionStore>,
+}
+
+impl OidcLoginHandler {
+    pub fn new(config: OidcLoginConfig) -> Result<Self, OidcError> {
+        let callback_path = Url::parse(&config.redirect_uri)
+            .map_err(|e| OidcError::ConfigError(e.to_string()))?
+            .path()
+            .to_string();
+
+        let session = &config.session;
+        let store: Box<dyn SessionStore> = match session.store {
+            SessionStoreKind::Cookie => Box::new(CookieSessionStore {
+                cipher: CookieCipher::new(&session.encryption_key),
+            }),
+            SessionStoreKind::Redis => {
+                let redis_url = session.redis_url.as_deref()
+                    .ok_or_else(|| OidcError::ConfigError("redis_url is required for redis sessions".into()))?;
+                Box::new(RedisSessionStore {
+                    client: redis::Client::open(redis_url)?,
+                    cipher: CookieCipher::new(&session.encryption_key),
+                    ttl_seconds: session.ttl_seconds,
+                })
+            }
+        };
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            cipher: CookieCipher::new(&session.encryption_key),
+            callback_path,
+            store,
+            config,
+        })
+    }
+
+    pub fn callback_path(&self) -> &str {
+        &self.callback_path
+    }
+
+    fn login_cookie_name(&self) -> String {
+        format!("{}_login", self.config.session.cookie_name)
+    }
+
+    pub async fn authenticate(&self, req: &ApiRequest) -> Result<OidcOutcome, OidcError> {
+        let cookie_name = &self.config.session.cookie_name;
+        let cookie_value = read_cookie(req, cookie_name);
+
+        let session = match &cookie_value {
+            Some(value) => self.store.load(value).await?,
+            None => None,
+        };
+
+        let Some(session) = session else {
+            return self.begin_login(req).map(OidcOutcome::Redirect);
+        };
+
+        if !session.needs_refresh() {
+            return Ok(OidcOutcome::Authenticated {
+                access_token: session.access_token,
+                set_cookie: None,
+            });
+        }
+
+        let Some(refresh_token) = session.refresh_token.as_deref() else {
+            return self.begin_login(req).map(OidcOutcome::Redirect);
+        };
+
+        match self.request_tokens(&[("grant_type", "refresh_token"), ("refresh_token", refresh_token)]).await {
+            Ok(mut refreshed) => {
+                // Providers are not required to rotate refresh tokens
+                if refreshed.refresh_token.is_none() {
+                    refreshed.refresh_token = session.refresh_token.clone();
+                }
+                let value = self.store.save(cookie_value.as_deref(), &refreshed).await?;
+                Ok(OidcOutcome::Authenticated {
+                    access_token: refreshed.access_token,
+                    set_cookie: Some(self.build_cookie(cookie_name, &value, self.config.session.ttl_seconds)?),
+                })
+            }
+            Err(e) => {
+                log::info!("OIDC token refresh failed, restarting login: {}", e);
+                self.begin_login(req).map(OidcOutcome::Redirect)
+            }
+        }
+    }
+
+    fn begin_login(&self, req: &ApiRequest) -> Result<ApiResponse, OidcError> {
+        // Only browsers get redirected, API clients receive a plain 401
+        let accepts_html = req.headers.get(ACCEPT)
+            .and_then(|h| h.to_str().ok())
+            .map(|accept| accept.contains("text/html"))
+            .unwrap_or(false);
+        if !accepts_html {
+            return Err(OidcError::Unauthenticated);
+        }
+
+        let code_verifier = random_token();
+        let pending = PendingLogin {
+            state: random_token(),
+            code_verifier: code_verifier.clone(),
+            return_to: local_path(req.uri.path_and_query().map_or("/", |pq| pq.as_str())).to_string(),
+            expires_at: now() + LOGIN_STATE_TTL,
+        };
+
+        let mut url = Url::parse(&self.config.authorization_endpoint)
+            .map_err(|e| OidcError::ConfigError(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", &pending.state)
+            .append_pair("code_challenge", &pkce_challenge(&code_verifier))
+            .append_pair("code_challenge_method", "S256");
+
+        let plain = serde_json::to_vec(&pending)
+            .map_err(|e| OidcError::SessionError(e.to_string()))?;
+        let login_cookie = self.build_cookie(&self.login_cookie_name(), &self.cipher.seal(&plain)?, LOGIN_STATE_TTL)?;
+
+        let mut response = ApiResponse::new(StatusCode::FOUND);
+        response.headers.insert(LOCATION, header_value(url.as_str())?);
+        response.headers.append(SET_COOKIE, login_cookie);
+        Ok(response)
+    }
+
+    pub async fn handle_callback(&self, req: &ApiRequest) -> Result<ApiResponse, OidcError> {
+        let params: std::collections::HashMap<String, String> = req.uri.query()
+            .map(|q| url::form_urlencoded::parse(q.as_bytes()).into_owned().collect())
+            .unwrap_or_default();
+
+        if let Some(error) = params.get("error") {
+            return Err(OidcError::AuthorizationError(error.clone()));
+        }
+
+        let pending: PendingLogin = read_cookie(req, &self.login_cookie_name())
+            .and_then(|value| self.cipher.open(&value))
+            .and_then(|plain| serde_json::from_slice(&plain).ok())
+            .ok_or(OidcError::InvalidState)?;
+
+        if pending.expires_at < now() || params.get("state") != Some(&pending.state) {
+            return Err(OidcError::InvalidState);
+        }
+
+        let code = params.get("code").ok_or(OidcError::InvalidState)?;
+        let session = self.request_tokens(&[
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("code_verifier", pending.code_verifier.as_str()),
+        ]).await?;
+
+        let cookie_name = &self.config.session.cookie_name;
+        let value = self.store.save(None, &session).await?;
+
+        let mut response = ApiResponse::new(StatusCode::FOUND);
+        response.headers.insert(LOCATION, header_value(local_path(&pending.return_to))?);
+        response.headers.append(SET_COOKIE, self.build_cookie(cookie_name, &value, self.config.session.ttl_seconds)?);
+        // Clear the one-time login state
+        response.headers.append(SET_COOKIE, self.build_cookie(&self.login_cookie_name(), "", 0)?);
+        Ok(response)
+    }
+
+    async fn request_tokens(&self, grant: &[(&str, &str)]) -> Result<OidcSession, OidcError> {
+        let mut params = grant.to_vec();
+        params.push(("client_id", self.config.client_id.as_str()));
+        if let Some(secret) = &self.config.client_secret {
+            params.push(("client_secret", secret.as_str()));
+        }
+
+        let response = self.client
+            .post(&self.config.token_endpoint)
+            .form(&params)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(OidcError::TokenError(response.status().to_string()));
+        }
+
+        let tokens: TokenResponse = response.json().await?;
+        Ok(OidcSession {
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+            id_token: tokens.id_token,
+            expires_at: now() + tokens.expires_in.unwrap_or(300),
+        })
+    }
+
+    fn build_cookie(&self, name: &str, value: &str, max_age: u64) -> Result<HeaderValue, OidcError> {
+        let secure = if self.config.session.secure_cookie { "; Secure" } else { "" };
+        // SameSite=Lax so the cookie survives the top-level redirect back from the IdP
+        header_value(&format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}",
+            name, value, max_age, secure
+        ))
+    }
+}
+
+fn read_cookie(req: &ApiRequest, name: &str) -> Option<String> {
+    req.headers.get_all(COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+// Post-login redirects stay on this origin: "//host" and "/\host" are
+// treated by browsers as links to another host
+fn local_path(target: &str) -> &str {
+    let mut chars = target.chars();
+    match (chars.next(), chars.next()) {
+        (Some('/'), Some('/' | '\\')) => "/",
+        (Some('/'), _) => target,
+        _ => "/",
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, OidcError> {
+    HeaderValue::from_str(value).map_err(|e| OidcError::SessionError(e.to_string()))
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookie_cipher_roundtrip() {
+        let cipher = CookieCipher::new("0123456789abcdef0123456789abcdef");
+        let sealed = cipher.seal(b"session").unwrap();
+
+        assert_eq!(cipher.open(&sealed).unwrap(), b"session");
+        assert!(CookieCipher::new("another-key-another-key-another-k").open(&sealed).is_none());
+    }
+
+    #[test]
+    fn test_return_to_stays_local() {
+        assert_eq!(local_path("/orders?page=2"), "/orders?page=2");
+        assert_eq!(local_path("/"), "/");
+        assert_eq!(local_path("//evil.example/login"), "/");
+        assert_eq!(local_path("/\\evil.example"), "/");
+        assert_eq!(local_path("https://evil.example"), "/");
+        assert_eq!(local_path(""), "/");
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // RFC 7636 appendix B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeJ1t9ZzevXAtmIQZxR8"
+        );
+    }
+}
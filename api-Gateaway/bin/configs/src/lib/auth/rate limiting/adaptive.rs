@@ -0,0 +1,252 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use crate::models::config::{AdaptiveAlgorithm, AdaptiveConcurrencyConfig};
+
+// Weight of a new sample in the long-term RTT baseline used by the gradient
+const BASELINE_WEIGHT: f64 = 0.05;
+// Shortest window over which the error rate is judged
+const MIN_WINDOW: Duration = Duration::from_millis(10);
+
+struct LimitState {
+    limit: f64,
+    // Slow-moving RTT average in seconds, the gradient's no-load reference
+    baseline_rtt: Option<f64>,
+    // Outcomes since `window_start`, one RTT's worth at a time
+    window_start: Instant,
+    samples: u32,
+    errors: u32,
+}
+
+// Concurrency limit for one upstream, adjusted from the RTT and outcome of
+// every request so load is shed before the backend saturates.
+pub struct AdaptiveLimiter {
+    upstream: String,
+    config: AdaptiveConcurrencyConfig,
+    in_flight: AtomicU32,
+    // Published copy of `state.limit` so admission never takes the lock
+    current_limit: AtomicU32,
+    state: Mutex<LimitState>,
+}
+
+// One admitted request; report how it went with `success` or `dropped`
+pub struct AdaptiveToken {
+    limiter: Arc<AdaptiveLimiter>,
+    in_flight: u32,
+}
+
+impl AdaptiveToken {
+    pub fn success(self, rtt: Duration) {
+        self.limiter.on_sample(rtt, self.in_flight, false);
+    }
+
+    // Errors, timeouts and overload responses from the upstream
+    pub fn dropped(self) {
+        self.limiter.on_sample(Duration::ZERO, self.in_flight, true);
+    }
+}
+
+impl Drop for AdaptiveToken {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl AdaptiveLimiter {
+    pub fn new(upstream: impl Into<String>, config: &AdaptiveConcurrencyConfig) -> Self {
+        let limit = config.initial_limit.clamp(config.min_limit, config.max_limit.max(config.min_limit));
+        let limiter = Self {
+            upstream: upstream.into(),
+            config: config.clone(),
+            in_flight: AtomicU32::new(0),
+            current_limit: AtomicU32::new(limit),
+            state: Mutex::new(LimitState {
+                limit: limit as f64,
+                baseline_rtt: None,
+                window_start: Instant::now(),
+                samples: 0,
+                errors: 0,
+            }),
+        };
+        limiter.publish(limit);
+        limiter
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.current_limit.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> u32 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn try_acquire(self: &Arc<Self>) -> Option<AdaptiveToken> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        if in_flight > self.limit() {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        Some(AdaptiveToken {
+            limiter: self.clone(),
+            in_flight,
+        })
+    }
+
+    fn on_sample(&self, rtt: Duration, in_flight: u32, dropped: bool) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let limit = state.limit;
+        // Growing a limit that is not being used says nothing about the upstream
+        let saturated = in_flight as f64 * 2.0 >= limit;
+        // AIMD treats slower responses as overload
+        let too_slow = rtt > Duration::from_millis(self.config.latency_threshold_ms);
+        let failed = dropped || (matches!(self.config.algorithm, AdaptiveAlgorithm::Aimd) && too_slow);
+
+        state.samples += 1;
+        if failed {
+            state.errors += 1;
+        } else {
+            let rtt = rtt.as_secs_f64().max(f64::EPSILON);
+            state.baseline_rtt = Some(state.baseline_rtt
+                .map_or(rtt, |b| b * (1.0 - BASELINE_WEIGHT) + rtt * BASELINE_WEIGHT));
+        }
+
+        // Backoff is decided from the error rate of a whole RTT window, so a burst
+        // of failures from requests that were in flight together backs off once
+        let window = state.baseline_rtt
+            .map_or(Duration::from_millis(self.config.latency_threshold_ms), Duration::from_secs_f64)
+            .max(MIN_WINDOW);
+        let mut overloaded = false;
+        if now.duration_since(state.window_start) >= window {
+            overloaded = state.errors as f64 / state.samples as f64 > self.config.max_error_rate;
+            state.window_start = now;
+            state.samples = 0;
+            state.errors = 0;
+        }
+
+        let new_limit = if overloaded {
+            limit * self.config.backoff_ratio
+        } else if failed || !saturated {
+            limit
+        } else {
+            match self.config.algorithm {
+                AdaptiveAlgorithm::Aimd => limit + 1.0,
+                AdaptiveAlgorithm::Gradient => {
+                    let rtt = rtt.as_secs_f64().max(f64::EPSILON);
+                    let baseline = state.baseline_rtt.unwrap_or(rtt);
+                    let gradient = (baseline / rtt).clamp(0.5, 1.0);
+                    // Headroom lets the limit probe upwards while latency is flat
+                    let target = limit * gradient + limit.sqrt();
+                    limit * (1.0 - self.config.smoothing) + target * self.config.smoothing
+                }
+            }
+        };
+
+        state.limit = new_limit.clamp(self.config.min_limit as f64, self.config.max_limit as f64);
+
+        let published = state.limit as u32;
+        if published != self.limit() {
+            self.current_limit.store(published, Ordering::Relaxed);
+            self.publish(published);
+        }
+    }
+
+    fn publish(&self, limit: u32) {
+        metrics::gauge!("gateway_adaptive_concurrency_limit", limit as f64, "upstream" => self.upstream.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(algorithm: AdaptiveAlgorithm) -> AdaptiveConcurrencyConfig {
+        AdaptiveConcurrencyConfig {
+            algorithm,
+            initial_limit: 10,
+            min_limit: 2,
+            max_limit: 100,
+            backoff_ratio: 0.5,
+            max_error_rate: 0.1,
+            latency_threshold_ms: 100,
+            smoothing: 0.5,
+        }
+    }
+
+    #[test]
+    fn test_aimd_sheds_and_recovers() {
+        let limiter = Arc::new(AdaptiveLimiter::new("reports", &config(AdaptiveAlgorithm::Aimd)));
+
+        let tokens: Vec<_> = (0..10).map(|_| limiter.try_acquire().unwrap()).collect();
+        assert!(limiter.try_acquire().is_none());
+
+        for token in tokens {
+            token.success(Duration::from_millis(50));
+        }
+        assert!(limiter.limit() > 10);
+        assert_eq!(limiter.in_flight(), 0);
+
+        // A burst of failures backs off once, when its RTT window closes
+        let before = limiter.limit();
+        for _ in 0..20 {
+            limiter.try_acquire().unwrap().dropped();
+        }
+        std::thread::sleep(Duration::from_millis(60));
+        limiter.try_acquire().unwrap().dropped();
+        assert_eq!(limiter.limit(), before / 2);
+
+        // Slow responses count as overload too
+        for _ in 0..2 {
+            limiter.try_acquire().unwrap().success(Duration::from_millis(500));
+            std::thread::sleep(Duration::from_millis(60));
+        }
+        assert!(limiter.limit() < before / 2);
+    }
+
+    #[test]
+    fn test_occasional_errors_do_not_back_off() {
+        let limiter = Arc::new(AdaptiveLimiter::new("reports", &config(AdaptiveAlgorithm::Aimd)));
+
+        let tokens: Vec<_> = (0..10).map(|_| limiter.try_acquire().unwrap()).collect();
+        for token in tokens {
+            token.success(Duration::from_millis(50));
+        }
+        let before = limiter.limit();
+
+        // One failure in twenty stays under the 10% error rate
+        for i in 0..20 {
+            let token = limiter.try_acquire().unwrap();
+            if i == 0 {
+                token.dropped();
+            } else {
+                token.success(Duration::from_millis(50));
+            }
+        }
+        std::thread::sleep(Duration::from_millis(60));
+        limiter.try_acquire().unwrap().success(Duration::from_millis(50));
+        assert!(limiter.limit() >= before);
+    }
+
+    #[test]
+    fn test_gradient_follows_latency() {
+        let limiter = Arc::new(AdaptiveLimiter::new("reports", &config(AdaptiveAlgorithm::Gradient)));
+
+        let run = |rtt: Duration| {
+            let tokens: Vec<_> = (0..limiter.limit()).filter_map(|_| limiter.try_acquire()).collect();
+            for token in tokens {
+                token.success(rtt);
+            }
+        };
+
+        run(Duration::from_millis(10));
+        let healthy = limiter.limit();
+        assert!(healthy >= 10);
+
+        for _ in 0..3 {
+            run(Duration::from_millis(200));
+        }
+        assert!(limiter.limit() < healthy);
+        assert!(limiter.limit() >= 2);
+    }
+}
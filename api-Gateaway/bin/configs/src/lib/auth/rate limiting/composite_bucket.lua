@@ -0,0 +1,54 @@
+-- Token buckets for every sub-limit of a composite policy.
+-- A request is only charged when all buckets have a token, so the
+-- limits are checked and consumed atomically.
+-- ARGV holds (capacity, refill_amount, refill_seconds, burst) per key,
+-- followed by the request cost and the debit flag.
+local now = tonumber(redis.call('TIME')[1])
+local cost = tonumber(ARGV[#KEYS * 4 + 1] or 1)
+local force = ARGV[#KEYS * 4 + 2] == '1'
+local tokens = {}
+local refills = {}
+local allowed = 1
+
+for i, key in ipairs(KEYS) do
+    local base = (i - 1) * 4
+    local capacity = tonumber(ARGV[base + 1])
+    local refill_amount = tonumber(ARGV[base + 2])
+    local refill_seconds = tonumber(ARGV[base + 3])
+    local burst = tonumber(ARGV[base + 4])
+
+    local last_refill = tonumber(redis.call('GET', key..':last_refill') or now)
+    local current = tonumber(redis.call('GET', key..':tokens') or capacity)
+
+    local time_passed = now - last_refill
+    if time_passed > 0 then
+        local refill_count = math.floor(time_passed / refill_seconds) * refill_amount
+        current = math.min(current + refill_count, capacity + burst)
+        last_refill = now - (time_passed % refill_seconds)
+    end
+
+    tokens[i] = current
+    refills[i] = last_refill
+    if not force and current < cost then
+        allowed = 0
+    end
+end
+
+local remaining = -1
+local reset = 0
+for i, key in ipairs(KEYS) do
+    local refill_seconds = tonumber(ARGV[(i - 1) * 4 + 3])
+    if allowed == 1 then
+        tokens[i] = tokens[i] - cost
+    end
+    -- The reset reported is the one of the tightest bucket
+    if remaining < 0 or tokens[i] < remaining then
+        remaining = tokens[i]
+        reset = refill_seconds - (now - refills[i])
+    end
+
+    redis.call('SET', key..':tokens', tokens[i], 'EX', refill_seconds * 2)
+    redis.call('SET', key..':last_refill', refills[i], 'EX', refill_seconds * 2)
+end
+
+return { allowed, math.max(0, remaining), reset }
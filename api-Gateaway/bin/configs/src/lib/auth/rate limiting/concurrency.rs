@@ -0,0 +1,234 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use crate::models::config::ConcurrencyConfig;
+use crate::rate_limiting::redis_store::{RedisRateLimitError, RedisRateLimiter};
+
+// How often a queued request retries for a cluster-wide lease
+const LEASE_RETRY_INTERVAL: Duration = Duration::from_millis(25);
+
+#[derive(Debug, Error)]
+pub enum ConcurrencyError {
+    #[error("Too many requests waiting for {0}")]
+    QueueFull(String),
+    #[error("Timed out waiting for a slot on {0}")]
+    Timeout(String),
+    #[error("Lease store error: {0}")]
+    Store(#[from] RedisRateLimitError),
+}
+
+// Caps in-flight requests with a local semaphore and, when distributed,
+// a Redis lease per request so the cap holds across all instances.
+pub struct ConcurrencyLimiter {
+    name: String,
+    semaphore: Arc<Semaphore>,
+    waiting: AtomicU32,
+    max_in_flight: u32,
+    max_queue: u32,
+    queue_timeout: Duration,
+    lease_ttl: Duration,
+    redis: Option<RedisRateLimiter>,
+}
+
+// A cluster-wide slot, renewed in the background for as long as it is held
+struct Lease {
+    redis: RedisRateLimiter,
+    key: String,
+    id: String,
+    renewal: JoinHandle<()>,
+}
+
+impl Lease {
+    fn new(redis: RedisRateLimiter, key: String, id: String, ttl: Duration) -> Self {
+        let renewal = tokio::spawn({
+            let (redis, key, id) = (redis.clone(), key.clone(), id.clone());
+            async move {
+                // Renewed well before it lapses, so one failed attempt is survivable
+                let mut interval = tokio::time::interval((ttl / 3).max(LEASE_RETRY_INTERVAL));
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    match redis.renew_lease(&key, &id, ttl).await {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            log::warn!("Concurrency lease on {} expired while held", key);
+                            return;
+                        }
+                        Err(e) => log::warn!("Failed to renew concurrency lease on {}: {}", key, e),
+                    }
+                }
+            }
+        });
+
+        Self { redis, key, id, renewal }
+    }
+}
+
+// The slot is held until the permit is dropped
+pub struct ConcurrencyPermit {
+    _local: OwnedSemaphorePermit,
+    lease: Option<Lease>,
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        if let Some(Lease { redis, key, id: lease_id, renewal }) = self.lease.take() {
+            renewal.abort();
+            tokio::spawn(async move {
+                // An unreleased lease still expires after its TTL
+                if let Err(e) = redis.release_lease(&key, &lease_id).await {
+                    log::warn!("Failed to release concurrency lease on {}: {}", key, e);
+                }
+            });
+        }
+    }
+}
+
+struct QueueSlot<'a>(&'a AtomicU32);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(name: impl Into<String>, config: &ConcurrencyConfig, redis: Option<RedisRateLimiter>) -> Self {
+        Self {
+            name: name.into(),
+            semaphore: Arc::new(Semaphore::new(config.max_in_flight as usize)),
+            waiting: AtomicU32::new(0),
+            max_in_flight: config.max_in_flight,
+            max_queue: config.max_queue,
+            queue_timeout: Duration::from_millis(config.queue_timeout_ms),
+            lease_ttl: Duration::from_secs(config.lease_ttl_seconds),
+            redis: redis.filter(|_| config.distributed),
+        }
+    }
+
+    pub fn in_flight(&self) -> u32 {
+        self.max_in_flight.saturating_sub(self.semaphore.available_permits() as u32)
+    }
+
+    pub async fn acquire(&self) -> Result<ConcurrencyPermit, ConcurrencyError> {
+        let deadline = Instant::now() + self.queue_timeout;
+
+        let local = match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                let _slot = self.enter_queue()?;
+                tokio::time::timeout_at(deadline, self.semaphore.clone().acquire_owned())
+                    .await
+                    .ok()
+                    .and_then(Result::ok)
+                    .ok_or_else(|| ConcurrencyError::Timeout(self.name.clone()))?
+            }
+        };
+
+        let lease = match &self.redis {
+            Some(redis) => match self.acquire_lease(redis, deadline).await {
+                Ok(lease) => Some(lease),
+                // The local cap still protects the backend from this instance
+                Err(ConcurrencyError::Store(e)) => {
+                    log::warn!("Concurrency lease store unavailable for {}, enforcing locally: {}", self.name, e);
+                    None
+                }
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+
+        Ok(ConcurrencyPermit { _local: local, lease })
+    }
+
+    fn enter_queue(&self) -> Result<QueueSlot<'_>, ConcurrencyError> {
+        let slot = QueueSlot(&self.waiting);
+        if self.waiting.fetch_add(1, Ordering::SeqCst) >= self.max_queue {
+            return Err(ConcurrencyError::QueueFull(self.name.clone()));
+        }
+        Ok(slot)
+    }
+
+    async fn acquire_lease(
+        &self,
+        redis: &RedisRateLimiter,
+        deadline: Instant,
+    ) -> Result<Lease, ConcurrencyError> {
+        let key = format!("cc:{}", self.name);
+        let lease_id = uuid::Uuid::new_v4().to_string();
+        let mut slot = None;
+
+        loop {
+            if redis.acquire_lease(&key, &lease_id, self.max_in_flight, self.lease_ttl).await? {
+                return Ok(Lease::new(redis.clone(), key, lease_id, self.lease_ttl));
+            }
+
+            if slot.is_none() {
+                slot = Some(self.enter_queue()?);
+            }
+            if Instant::now() + LEASE_RETRY_INTERVAL > deadline {
+                return Err(ConcurrencyError::Timeout(self.name.clone()));
+            }
+            tokio::time::sleep(LEASE_RETRY_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::config::ConcurrencyScope;
+
+    fn config(max_in_flight: u32, max_queue: u32, queue_timeout_ms: u64) -> ConcurrencyConfig {
+        ConcurrencyConfig {
+            max_in_flight,
+            scope: ConcurrencyScope::Route,
+            distributed: false,
+            max_queue,
+            queue_timeout_ms,
+            lease_ttl_seconds: 60,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_without_queue() {
+        let limiter = ConcurrencyLimiter::new("reports", &config(1, 0, 100), None);
+
+        let permit = limiter.acquire().await.unwrap();
+        assert_eq!(limiter.in_flight(), 1);
+        assert!(matches!(limiter.acquire().await, Err(ConcurrencyError::QueueFull(_))));
+
+        drop(permit);
+        assert!(limiter.acquire().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_queued_request_gets_released_slot() {
+        let limiter = Arc::new(ConcurrencyLimiter::new("reports", &config(1, 1, 1_000), None));
+        let permit = limiter.acquire().await.unwrap();
+
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await.map(|_| ()) }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // The queue holds a single waiter
+        assert!(matches!(limiter.acquire().await, Err(ConcurrencyError::QueueFull(_))));
+
+        drop(permit);
+        assert!(waiter.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_queue_wait_is_bounded() {
+        let limiter = ConcurrencyLimiter::new("reports", &config(1, 5, 20), None);
+        let _permit = limiter.acquire().await.unwrap();
+
+        assert!(matches!(limiter.acquire().await, Err(ConcurrencyError::Timeout(_))));
+    }
+}
@@ -0,0 +1,21 @@
+-- Cluster-wide in-flight cap: one sorted-set member per lease, scored by
+-- its expiry so leases of crashed instances are reclaimed.
+-- ARGV: max_in_flight, ttl_ms, lease id
+local key = KEYS[1]
+local max_in_flight = tonumber(ARGV[1])
+local ttl = tonumber(ARGV[2])
+local lease = ARGV[3]
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+redis.call('ZREMRANGEBYSCORE', key, '-inf', now)
+local in_flight = redis.call('ZCARD', key)
+if in_flight >= max_in_flight then
+    return { 0, in_flight }
+end
+
+redis.call('ZADD', key, now + ttl, lease)
+redis.call('PEXPIRE', key, ttl)
+
+return { 1, in_flight + 1 }
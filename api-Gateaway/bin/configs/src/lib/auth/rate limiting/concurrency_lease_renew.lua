@@ -0,0 +1,19 @@
+-- Pushes back the expiry of a lease that is still held.
+-- ARGV: ttl_ms, lease id
+local key = KEYS[1]
+local ttl = tonumber(ARGV[1])
+local lease = ARGV[2]
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+-- A lease that already expired is not resurrected, its slot may be taken
+local expires = redis.call('ZSCORE', key, lease)
+if not expires or tonumber(expires) <= now then
+    return 0
+end
+
+redis.call('ZADD', key, now + ttl, lease)
+redis.call('PEXPIRE', key, ttl)
+
+return 1
@@ -0,0 +1,23 @@
+-- Fixed window counter aligned to multiples of the window length.
+-- ARGV: limit, window_seconds, cost, force
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local cost = tonumber(ARGV[3] or 1)
+local force = ARGV[4] == '1'
+
+local now = tonumber(redis.call('TIME')[1])
+local window_start = now - (now % window)
+local key = KEYS[1]..':'..window_start
+local reset = window_start + window - now
+
+local count = tonumber(redis.call('GET', key) or 0)
+if not force and count + cost > limit then
+    return { 0, math.max(0, limit - count), reset }
+end
+
+count = redis.call('INCRBY', key, cost)
+if count == cost then
+    redis.call('EXPIRE', key, window)
+end
+
+return { 1, math.max(0, limit - count), reset }
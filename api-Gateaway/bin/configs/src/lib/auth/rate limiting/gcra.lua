@@ -0,0 +1,31 @@
+-- Generic cell rate algorithm: a single theoretical arrival time (TAT)
+-- per key, in milliseconds. Requests are spaced window/limit apart and
+-- up to limit + burst may arrive back to back.
+-- ARGV: limit, window_seconds, burst, cost, force
+local key = KEYS[1]
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local burst = tonumber(ARGV[3])
+local cost = tonumber(ARGV[4] or 1)
+local force = ARGV[5] == '1'
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local interval = window * 1000 / limit
+local tolerance = interval * (limit + burst)
+
+local tat = tonumber(redis.call('GET', key) or now)
+tat = math.max(tat, now)
+
+local new_tat = math.ceil(tat + interval * cost)
+local allow_at = new_tat - tolerance
+
+if not force and now < allow_at then
+    return { 0, 0, math.ceil((allow_at - now) / 1000) }
+end
+
+redis.call('SET', key, new_tat, 'PX', math.ceil(new_tat - now))
+
+local remaining = math.max(0, math.floor((now - allow_at) / interval))
+return { 1, remaining, math.ceil((new_tat - now) / 1000) }
@@ -0,0 +1,111 @@
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeaderOptions {
+    // IETF draft `RateLimit-*` headers
+    #[serde(default = "default_true")]
+    pub standard: bool,
+    // `X-RateLimit-*` headers for older clients
+    #[serde(default)]
+    pub legacy: bool,
+}
+
+impl Default for HeaderOptions {
+    fn default() -> Self {
+        Self {
+            standard: true,
+            legacy: false,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// Outcome of a rate limit check, as reported to the client
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: i64,
+    pub remaining: i64,
+    // Seconds until the limit resets, or until the next request is allowed when denied
+    pub reset: u64,
+}
+
+impl RateLimitStatus {
+    pub fn apply(&self, headers: &mut HeaderMap, options: &HeaderOptions) {
+        let limit = HeaderValue::from(self.limit.max(0));
+        let remaining = HeaderValue::from(self.remaining.max(0));
+
+        if options.standard {
+            headers.insert(RATELIMIT_LIMIT, limit.clone());
+            headers.insert(RATELIMIT_REMAINING, remaining.clone());
+            headers.insert(RATELIMIT_RESET, HeaderValue::from(self.reset));
+        }
+
+        if options.legacy {
+            // The legacy convention is an absolute epoch timestamp rather than a delta
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            headers.insert(X_RATELIMIT_LIMIT, limit);
+            headers.insert(X_RATELIMIT_REMAINING, remaining);
+            headers.insert(X_RATELIMIT_RESET, HeaderValue::from(now + self.reset));
+        }
+
+        if !self.allowed {
+            headers.insert(RETRY_AFTER, HeaderValue::from(self.reset.max(1)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_denied_status_headers() {
+        let status = RateLimitStatus {
+            allowed: false,
+            limit: 100,
+            remaining: 0,
+            reset: 0,
+        };
+        let mut headers = HeaderMap::new();
+        status.apply(&mut headers, &HeaderOptions { standard: true, legacy: true });
+
+        assert_eq!(headers["ratelimit-limit"], "100");
+        assert_eq!(headers["ratelimit-remaining"], "0");
+        assert_eq!(headers["ratelimit-reset"], "0");
+        assert!(headers.contains_key("x-ratelimit-reset"));
+        // Never tell a client to retry immediately
+        assert_eq!(headers[RETRY_AFTER], "1");
+    }
+
+    #[test]
+    fn test_allowed_status_has_no_retry_after() {
+        let status = RateLimitStatus {
+            allowed: true,
+            limit: 10,
+            remaining: 7,
+            reset: 30,
+        };
+        let mut headers = HeaderMap::new();
+        status.apply(&mut headers, &HeaderOptions::default());
+
+        assert_eq!(headers["ratelimit-remaining"], "7");
+        assert!(!headers.contains_key(RETRY_AFTER));
+        assert!(!headers.contains_key("x-ratelimit-limit"));
+    }
+}
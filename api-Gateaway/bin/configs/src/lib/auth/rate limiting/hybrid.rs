@@ -0,0 +1,148 @@
+use crate::rate_limiting::policy::{CompiledPolicy, CompiledRule, StoreFailureMode};
+use crate::rate_limiting::redis_store::{RateLimitConfig, RedisRateLimitError, RedisRateLimiter};
+use crate::rate_limiting::token_bucket::RateLimitError;
+
+// Redis is the source of truth; in-process token buckets shed load before it
+// (`local_first`) and keep limits enforced per instance while it is unreachable.
+pub struct HybridRateLimiter {
+    redis: Option<RedisRateLimiter>,
+}
+
+impl HybridRateLimiter {
+    pub fn new(redis: RedisRateLimiter) -> Self {
+        Self { redis: Some(redis) }
+    }
+
+    // Single-node and development setups without Redis
+    pub fn local() -> Self {
+        Self { redis: None }
+    }
+
+    pub fn redis(&self) -> Option<&RedisRateLimiter> {
+        self.redis.as_ref()
+    }
+
+    // Returns (allowed, remaining, reset) across all rules of the policy for a
+    // request costing `cost` units. Errors only surface for policies that fail closed.
+    pub async fn check(
+        &self,
+        policy: &CompiledPolicy,
+        rules: &[(String, &CompiledRule)],
+        cost: u32,
+    ) -> Result<(bool, i64, u64), RedisRateLimitError> {
+        let Some(redis) = &self.redis else {
+            return Ok(Self::check_local(rules, cost));
+        };
+
+        let mut local = None;
+        if policy.local_first {
+            let result = Self::check_local(rules, cost);
+            if !result.0 {
+                return Ok(result);
+            }
+            local = Some(result);
+        }
+
+        let error = match Self::check_redis(redis, rules, cost).await {
+            Ok(result) => return Ok(result),
+            Err(e) => e,
+        };
+
+        match policy.on_store_failure {
+            StoreFailureMode::Allow => {
+                log::warn!("Rate limit store unavailable, allowing request for policy {}: {}", policy.name, error);
+                let limit = rules.iter().map(|(_, rule)| rule.config.capacity).min().unwrap_or_default();
+                Ok((true, limit, 0))
+            }
+            StoreFailureMode::Deny => Err(error),
+            StoreFailureMode::Local => {
+                log::warn!("Rate limit store unavailable, enforcing policy {} locally: {}", policy.name, error);
+                match local {
+                    // Already charged by the first tier
+                    Some(result) => Ok(result),
+                    None => Ok(Self::check_local(rules, cost)),
+                }
+            }
+        }
+    }
+
+    // Charges a cost learned after the request was let through. It is never
+    // rejected; the overdraft is paid off by the consumer's next requests.
+    pub async fn debit(&self, policy: &CompiledPolicy, rules: &[(String, &CompiledRule)], cost: u32) {
+        if cost == 0 {
+            return;
+        }
+
+        let Some(redis) = &self.redis else {
+            Self::debit_local(rules, cost);
+            return;
+        };
+
+        // The first tier would otherwise keep admitting the consumer
+        if policy.local_first {
+            Self::debit_local(rules, cost);
+        }
+
+        let result = match rules {
+            [] => return,
+            [(key, rule)] => redis.debit(key, &rule.config, &rule.algorithm, cost).await,
+            _ => redis.debit_composite(&Self::limits(rules), cost).await,
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to debit {} units from policy {}: {}", cost, policy.name, e);
+            if policy.on_store_failure == StoreFailureMode::Local && !policy.local_first {
+                Self::debit_local(rules, cost);
+            }
+        }
+    }
+
+    async fn check_redis(redis: &RedisRateLimiter, rules: &[(String, &CompiledRule)], cost: u32) -> Result<(bool, i64, u64), RedisRateLimitError> {
+        match rules {
+            [(key, rule)] => redis.check_n(key, &rule.config, &rule.algorithm, cost).await,
+            _ => redis.check_composite_rate_limit(&Self::limits(rules), cost).await,
+        }
+    }
+
+    fn limits(rules: &[(String, &CompiledRule)]) -> Vec<(String, RateLimitConfig)> {
+        rules.iter()
+            .map(|(key, rule)| (key.clone(), rule.config.clone()))
+            .collect()
+    }
+
+    fn debit_local(rules: &[(String, &CompiledRule)], cost: u32) {
+        for (key, rule) in rules {
+            rule.local.debit(key, cost as u64);
+        }
+    }
+
+    fn check_local(rules: &[(String, &CompiledRule)], cost: u32) -> (bool, i64, u64) {
+        let mut charged = Vec::with_capacity(rules.len());
+        let mut remaining = i64::MAX;
+        let mut reset = 0;
+
+        for (key, rule) in rules {
+            match rule.local.try_acquire_n(key, cost as u64) {
+                Ok(left) => {
+                    charged.push((key, rule));
+                    if (left as i64) < remaining {
+                        remaining = left as i64;
+                        reset = rule.config.refill_seconds;
+                    }
+                }
+                Err(e) => {
+                    // Like the composite script, charge every bucket or none
+                    for (key, rule) in charged {
+                        rule.local.release(key, cost as u64);
+                    }
+                    let reset = match e {
+                        RateLimitError::RetryAfter(wait) => wait.as_secs_f64().ceil() as u64,
+                        _ => rule.config.refill_seconds,
+                    };
+                    return (false, 0, reset);
+                }
+            }
+        }
+
+        (true, if charged.is_empty() { 0 } else { remaining }, reset)
+    }
+}
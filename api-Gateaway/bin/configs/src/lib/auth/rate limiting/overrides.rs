@@ -0,0 +1,334 @@
+use futures::StreamExt;
+use redis::{AsyncCommands, RedisError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use crate::models::ApiRequest;
+use crate::rate_limiting::policy::{PolicyError, RateLimitPolicies};
+use crate::rate_limiting::redis_store::RedisConnection;
+
+const CHANGES_CHANNEL: &str = "gateway:rate-limits:overrides";
+// Attempts at an edit that raced with one on another instance
+const MAX_EDIT_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Error)]
+#[error("Invalid CIDR `{0}`")]
+pub struct InvalidCidr(String);
+
+// IPv4 or IPv6 network; a bare address is a single-host network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCidr(s.to_string());
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+
+        let network: IpAddr = address.trim().parse().map_err(|_| invalid())?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+
+        Ok(Self { network, prefix })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = InvalidCidr;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverrideMatch {
+    Cidr(Cidr),
+    // Consumer name of an API key accepted by the API key authenticator
+    ApiKey(String),
+    JwtClaim { claim: String, value: String },
+}
+
+impl OverrideMatch {
+    fn matches(&self, req: &ApiRequest) -> bool {
+        match self {
+            // The client address, which only comes from X-Forwarded-For behind a trusted proxy
+            OverrideMatch::Cidr(cidr) => req.remote_addr.is_some_and(|addr| cidr.contains(addr.ip())),
+            OverrideMatch::ApiKey(consumer) => req.identity.as_ref()
+                .is_some_and(|claims| claims.iss == "gateway-api-key" && claims.sub == *consumer),
+            OverrideMatch::JwtClaim { claim, value } => req.identity.as_ref()
+                .and_then(|claims| serde_json::to_value(claims).ok())
+                .and_then(|claims| match claims.get(claim.as_str())? {
+                    serde_json::Value::String(s) => Some(s == value),
+                    other => Some(other.to_string() == *value),
+                })
+                .unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverrideAction {
+    // Skip rate limiting altogether, e.g. for health probes
+    Bypass,
+    // Count against another, usually more generous, policy
+    Policy(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitOverride {
+    // Taken from the URL when set through the admin API
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "match")]
+    pub matcher: OverrideMatch,
+    pub action: OverrideAction,
+    // Policies the override applies to; all of them when empty
+    #[serde(default)]
+    pub policies: Vec<String>,
+}
+
+impl RateLimitOverride {
+    pub fn applies(&self, policy: &str, req: &ApiRequest) -> bool {
+        (self.policies.is_empty() || self.policies.iter().any(|p| p == policy))
+            && self.matcher.matches(req)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OverrideError {
+    #[error(transparent)]
+    Policy(#[from] PolicyError),
+    #[error("Unknown override `{0}`")]
+    NotFound(String),
+    #[error("Overrides kept changing concurrently")]
+    Conflict,
+    #[error("Redis error: {0}")]
+    Redis(#[from] RedisError),
+    #[error("Invalid stored overrides: {0}")]
+    Encoding(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChangeMessage {
+    // Instances skip their own messages
+    origin: String,
+}
+
+// Overrides are stored in Redis as one ordered list shared by every instance;
+// rate_limits.yaml only seeds a store that holds none yet. Edits are broadcast
+// over pub/sub so other instances reload them right away.
+pub struct OverrideStore {
+    policies: Arc<RateLimitPolicies>,
+    conn: RedisConnection,
+    key: String,
+    instance_id: String,
+    script: redis::Script,
+}
+
+impl OverrideStore {
+    pub fn new(policies: Arc<RateLimitPolicies>, conn: RedisConnection) -> Self {
+        Self {
+            key: format!("{}overrides", policies.key_prefix()),
+            policies,
+            conn,
+            instance_id: uuid::Uuid::new_v4().to_string(),
+            script: redis::Script::new(include_str!("overrides_update.lua")),
+        }
+    }
+
+    pub fn list(&self) -> Vec<RateLimitOverride> {
+        self.policies.overrides()
+    }
+
+    // Reloads the overrides from Redis, seeding it with the configured ones on first use
+    pub async fn sync(&self) -> Result<(), OverrideError> {
+        let mut conn = self.conn.clone();
+        let seed = serde_json::to_string(&self.policies.overrides())?;
+        let _: () = conn.set_nx(&self.key, seed).await?;
+        let (_, rules) = self.load().await?;
+
+        let rules = rules.into_iter()
+            .filter(|rule| match self.policies.validate_override(rule) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!("Ignoring stored rate limit override: {}", e);
+                    false
+                }
+            })
+            .collect();
+        self.policies.replace_overrides(rules);
+        Ok(())
+    }
+
+    async fn load(&self) -> Result<(String, Vec<RateLimitOverride>), OverrideError> {
+        let mut conn = self.conn.clone();
+        let stored: Option<String> = conn.get(&self.key).await?;
+        let rules = match &stored {
+            Some(stored) => serde_json::from_str(stored)?,
+            None => self.policies.overrides(),
+        };
+        Ok((stored.unwrap_or_default(), rules))
+    }
+
+    // Replaces the override with the same name, or appends it, on every instance
+    pub async fn put(&self, rule: RateLimitOverride) -> Result<(), OverrideError> {
+        self.policies.validate_override(&rule)?;
+        self.edit(|rules| {
+            match rules.iter_mut().find(|existing| existing.name == rule.name) {
+                Some(existing) => *existing = rule.clone(),
+                None => rules.push(rule.clone()),
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn remove(&self, name: &str) -> Result<(), OverrideError> {
+        self.edit(|rules| {
+            let before = rules.len();
+            rules.retain(|rule| rule.name != name);
+            if rules.len() == before {
+                return Err(OverrideError::NotFound(name.to_string()));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    // Read-modify-write of the stored list, retried when another instance edited it meanwhile
+    async fn edit(&self, change: impl Fn(&mut Vec<RateLimitOverride>) -> Result<(), OverrideError>) -> Result<(), OverrideError> {
+        let mut conn = self.conn.clone();
+        for _ in 0..MAX_EDIT_ATTEMPTS {
+            let (stored, mut rules) = self.load().await?;
+            change(&mut rules)?;
+
+            let replaced: bool = self.script
+                .key(&self.key)
+                .arg(stored)
+                .arg(serde_json::to_string(&rules)?)
+                .invoke_async(&mut conn)
+                .await?;
+            if replaced {
+                self.policies.replace_overrides(rules);
+                self.publish().await;
+                return Ok(());
+            }
+        }
+        Err(OverrideError::Conflict)
+    }
+
+    async fn publish(&self) {
+        let mut conn = self.conn.clone();
+        let message = serde_json::to_string(&ChangeMessage { origin: self.instance_id.clone() })
+            .expect("change message serializes");
+        if let Err(e) = conn.publish::<_, _, ()>(CHANGES_CHANNEL, message).await {
+            log::warn!("Cannot broadcast rate limit override change: {}", e);
+        }
+    }
+
+    // Reloads the overrides whenever another instance edits them, until the process exits
+    pub async fn listen_for_changes(self: Arc<Self>, client: redis::Client) {
+        loop {
+            if let Err(e) = self.apply_changes(&client).await {
+                log::warn!("Rate limit override subscription lost, resubscribing: {}", e);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn apply_changes(&self, client: &redis::Client) -> Result<(), OverrideError> {
+        let mut pubsub = client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(CHANGES_CHANNEL).await?;
+        // Anything edited while unsubscribed, and the stored list on startup
+        self.sync().await?;
+
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let payload: String = message.get_payload()?;
+            match serde_json::from_str::<ChangeMessage>(&payload) {
+                Ok(message) if message.origin == self.instance_id => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!("Ignoring rate limit override message: {}", e);
+                    continue;
+                }
+            }
+            if let Err(e) = self.sync().await {
+                log::warn!("Cannot reload rate limit overrides: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr_contains() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(cidr.contains("10.42.0.1".parse().unwrap()));
+        assert!(!cidr.contains("11.0.0.1".parse().unwrap()));
+        assert!(!cidr.contains("::1".parse().unwrap()));
+
+        let host: Cidr = "192.168.1.1".parse().unwrap();
+        assert_eq!(host.to_string(), "192.168.1.1/32");
+        assert!(!host.contains("192.168.1.2".parse().unwrap()));
+
+        let v6: Cidr = "fd00::/8".parse().unwrap();
+        assert!(v6.contains("fd12:3456::1".parse().unwrap()));
+
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains("8.8.8.8".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    }
+}
@@ -0,0 +1,8 @@
+-- Replaces the stored override list only if nobody changed it since it was read.
+-- ARGV: the list as read ('' when there was none), the new list
+local current = redis.call('GET', KEYS[1]) or ''
+if current ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2])
+return 1
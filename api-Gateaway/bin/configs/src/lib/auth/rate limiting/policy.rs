@@ -0,0 +1,635 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use crate::models::ApiRequest;
+use crate::models::config::RateLimitAlgorithm;
+use crate::rate_limiting::headers::HeaderOptions;
+use crate::rate_limiting::overrides::{OverrideAction, RateLimitOverride};
+use crate::rate_limiting::redis_store::RateLimitConfig;
+use crate::rate_limiting::token_bucket::{KeyedTokenBucket, RateLimitError};
+
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error("Cannot read rate limit policies: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid rate limit policies: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("Unknown key template variable `{0}`")]
+    UnknownVariable(String),
+    #[error("Policy `{0}` is invalid: {1}")]
+    InvalidPolicy(String, String),
+    #[error("Override `{0}` refers to unknown policy `{1}`")]
+    UnknownOverridePolicy(String, String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyType {
+    #[default]
+    Ip,
+    JwtClaim,
+    Header,
+    Composite,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyMode {
+    #[default]
+    Enforce,
+    // Evaluate and report would-be rejections without blocking, to roll out new limits
+    Shadow,
+}
+
+// What to do when the shared Redis store cannot be reached
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreFailureMode {
+    Allow,
+    Deny,
+    // Enforce the policy per gateway instance with in-process buckets
+    Local,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyRule {
+    #[serde(rename = "type", default)]
+    pub kind: PolicyType,
+    pub claim: Option<String>,
+    pub header: Option<String>,
+    #[serde(default)]
+    pub limit: u32,
+    #[serde(default = "default_window", with = "humantime_serde")]
+    pub window: Duration,
+    #[serde(default)]
+    pub burst: u32,
+    #[serde(default)]
+    pub algorithm: RateLimitAlgorithm,
+    pub key: Option<String>,
+    // Sub-limits of a composite policy
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+    pub on_store_failure: Option<StoreFailureMode>,
+    // Check an in-process bucket before Redis so floods are shed without a round trip
+    #[serde(default)]
+    pub local_first: bool,
+    #[serde(default)]
+    pub mode: PolicyMode,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyDefaults {
+    pub global: PolicyRule,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyFile {
+    #[serde(default)]
+    pub policies: HashMap<String, PolicyRule>,
+    pub defaults: Option<PolicyDefaults>,
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+    #[serde(default)]
+    pub headers: HeaderOptions,
+    // Fail closed when Redis is unavailable, unless a policy says otherwise
+    #[serde(default)]
+    pub strict_mode: bool,
+    // Checked in order; the first match wins
+    #[serde(default)]
+    pub overrides: Vec<RateLimitOverride>,
+}
+
+fn default_window() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_key_prefix() -> String {
+    "rl:".into()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    RemoteAddr,
+    JwtClaim(String),
+    Header(String),
+}
+
+// nginx-style key template: `$remote_addr`, `$jwt_claim.<name>` and `$http_<header>`
+#[derive(Debug, Clone)]
+pub struct KeyTemplate {
+    segments: Vec<Segment>,
+}
+
+impl KeyTemplate {
+    pub fn parse(template: &str) -> Result<Self, PolicyError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                literal.push(c);
+                continue;
+            }
+
+            let mut name = String::new();
+            while let Some(&next) = chars.peek() {
+                if next.is_ascii_alphanumeric() || next == '_' {
+                    name.push(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            let segment = if name == "remote_addr" {
+                Segment::RemoteAddr
+            } else if name == "jwt_claim" && chars.peek() == Some(&'.') {
+                chars.next();
+                let mut claim = String::new();
+                while let Some(&next) = chars.peek() {
+                    if next.is_ascii_alphanumeric() || next == '_' {
+                        claim.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                Segment::JwtClaim(claim)
+            } else if let Some(header) = name.strip_prefix("http_") {
+                Segment::Header(header.replace('_', "-"))
+            } else {
+                return Err(PolicyError::UnknownVariable(name));
+            };
+
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(segment);
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+
+    // Values missing from the request fall back to the client IP so they never share a bucket
+    pub fn render(&self, req: &ApiRequest) -> String {
+        let client_ip = || req.client_ip().unwrap_or_else(|| "unknown".into());
+
+        self.segments.iter()
+            .map(|segment| match segment {
+                Segment::Literal(s) => s.clone(),
+                Segment::RemoteAddr => client_ip(),
+                Segment::JwtClaim(claim) => req.identity.as_ref()
+                    .and_then(|claims| claims.claim(claim))
+                    .unwrap_or_else(client_ip),
+                Segment::Header(name) => req.headers.get(name.as_str())
+                    .and_then(|h| h.to_str().ok())
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(client_ip),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub template: KeyTemplate,
+    pub algorithm: RateLimitAlgorithm,
+    pub config: RateLimitConfig,
+    // In-process buckets for `local_first` and Redis-less operation
+    pub local: Arc<KeyedTokenBucket>,
+}
+
+impl CompiledRule {
+    fn new(template: KeyTemplate, algorithm: RateLimitAlgorithm, config: RateLimitConfig) -> Result<Self, RateLimitError> {
+        let local = KeyedTokenBucket::new(
+            (config.capacity + config.burst) as u64,
+            config.refill_amount as u64,
+            Duration::from_secs(config.refill_seconds),
+        )?;
+
+        Ok(Self {
+            template,
+            algorithm,
+            config,
+            local: Arc::new(local),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledPolicy {
+    pub name: String,
+    pub rules: Vec<CompiledRule>,
+    pub on_store_failure: StoreFailureMode,
+    pub local_first: bool,
+    pub mode: PolicyMode,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicies {
+    policies: HashMap<String, CompiledPolicy>,
+    default: CompiledPolicy,
+    key_prefix: String,
+    headers: HeaderOptions,
+    // Editable at runtime through the admin API
+    overrides: Arc<RwLock<Vec<RateLimitOverride>>>,
+}
+
+impl Default for RateLimitPolicies {
+    fn default() -> Self {
+        Self {
+            policies: HashMap::new(),
+            default: CompiledPolicy {
+                name: "default".into(),
+                rules: vec![CompiledRule::new(
+                    KeyTemplate { segments: vec![Segment::RemoteAddr] },
+                    RateLimitAlgorithm::TokenBucket,
+                    RateLimitConfig {
+                        capacity: 100,
+                        refill_amount: 100,
+                        refill_seconds: 60,
+                        burst: 20,
+                    },
+                ).expect("default rate limit policy is valid")],
+                on_store_failure: StoreFailureMode::Local,
+                local_first: false,
+                mode: PolicyMode::Enforce,
+            },
+            key_prefix: default_key_prefix(),
+            headers: HeaderOptions::default(),
+            overrides: Arc::default(),
+        }
+    }
+}
+
+impl RateLimitPolicies {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PolicyError> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_yaml(&contents)
+    }
+
+    pub fn from_yaml(contents: &str) -> Result<Self, PolicyError> {
+        let file: PolicyFile = serde_yaml::from_str(contents)?;
+        let on_store_failure = if file.strict_mode {
+            StoreFailureMode::Deny
+        } else {
+            StoreFailureMode::Local
+        };
+
+        let mut policies = HashMap::new();
+        for (name, rule) in &file.policies {
+            policies.insert(name.clone(), Self::compile(name, rule, on_store_failure)?);
+        }
+
+        let default = match &file.defaults {
+            Some(defaults) => Self::compile("default", &defaults.global, on_store_failure)?,
+            None => CompiledPolicy {
+                on_store_failure,
+                ..Self::default().default
+            },
+        };
+
+        let policies = Self {
+            policies,
+            default,
+            key_prefix: file.key_prefix,
+            headers: file.headers,
+            overrides: Arc::default(),
+        };
+        for rule in file.overrides {
+            policies.upsert_override(rule)?;
+        }
+        Ok(policies)
+    }
+
+    fn compile(name: &str, rule: &PolicyRule, on_store_failure: StoreFailureMode) -> Result<CompiledPolicy, PolicyError> {
+        let rules = if rule.kind == PolicyType::Composite {
+            if rule.rules.is_empty() {
+                return Err(PolicyError::InvalidPolicy(name.into(), "composite policy has no rules".into()));
+            }
+            // Sub-limits are charged atomically by a single token bucket script
+            if rule.rules.iter().any(|sub| sub.algorithm != RateLimitAlgorithm::TokenBucket) {
+                return Err(PolicyError::InvalidPolicy(name.into(), "composite policies only support token_bucket".into()));
+            }
+            rule.rules.iter()
+                .map(|sub| Self::compile_rule(name, sub))
+                .collect::<Result<_, _>>()?
+        } else {
+            vec![Self::compile_rule(name, rule)?]
+        };
+
+        Ok(CompiledPolicy {
+            name: name.to_string(),
+            rules,
+            on_store_failure: rule.on_store_failure.unwrap_or(on_store_failure),
+            local_first: rule.local_first,
+            mode: rule.mode,
+        })
+    }
+
+    fn compile_rule(name: &str, rule: &PolicyRule) -> Result<CompiledRule, PolicyError> {
+        if rule.limit == 0 || rule.window.as_secs() == 0 {
+            return Err(PolicyError::InvalidPolicy(name.into(), "limit and window must be positive".into()));
+        }
+
+        let template = match (&rule.key, &rule.kind) {
+            (Some(key), _) => KeyTemplate::parse(key)?,
+            (None, PolicyType::JwtClaim) => {
+                let claim = rule.claim.as_deref().unwrap_or("sub");
+                KeyTemplate::parse(&format!("$jwt_claim.{}", claim))?
+            }
+            (None, PolicyType::Header) => {
+                let header = rule.header.as_deref()
+                    .ok_or_else(|| PolicyError::InvalidPolicy(name.into(), "header policy without header".into()))?;
+                KeyTemplate { segments: vec![Segment::Header(header.to_ascii_lowercase())] }
+            }
+            (None, _) => KeyTemplate { segments: vec![Segment::RemoteAddr] },
+        };
+
+        CompiledRule::new(template, rule.algorithm.clone(), RateLimitConfig {
+            capacity: rule.limit as i64,
+            refill_amount: rule.limit as i64,
+            refill_seconds: rule.window.as_secs(),
+            burst: rule.burst as i64,
+        })
+        .map_err(|e| PolicyError::InvalidPolicy(name.into(), e.to_string()))
+    }
+
+    pub fn policy(&self, name: Option<&str>) -> &CompiledPolicy {
+        name.and_then(|n| self.policies.get(n)).unwrap_or(&self.default)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.policies.contains_key(name)
+    }
+
+    // Policy the request counts against after overrides; None when it bypasses limiting
+    pub fn resolve(&self, name: Option<&str>, req: &ApiRequest) -> Option<&CompiledPolicy> {
+        let policy = self.policy(name);
+        let action = self.overrides.read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find(|rule| rule.applies(&policy.name, req))
+            .map(|rule| rule.action.clone());
+
+        match action {
+            None => Some(policy),
+            Some(OverrideAction::Bypass) => None,
+            Some(OverrideAction::Policy(other)) => Some(self.policy(Some(&other))),
+        }
+    }
+
+    pub fn overrides(&self) -> Vec<RateLimitOverride> {
+        self.overrides.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub fn key_prefix(&self) -> &str {
+        &self.key_prefix
+    }
+
+    pub fn validate_override(&self, rule: &RateLimitOverride) -> Result<(), PolicyError> {
+        match &rule.action {
+            OverrideAction::Policy(target) if !self.contains(target) => {
+                Err(PolicyError::UnknownOverridePolicy(rule.name.clone(), target.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Swaps in the whole list, e.g. as loaded from the shared store
+    pub fn replace_overrides(&self, rules: Vec<RateLimitOverride>) {
+        *self.overrides.write().unwrap_or_else(PoisonError::into_inner) = rules;
+    }
+
+    // Replaces the override with the same name, or appends it
+    pub fn upsert_override(&self, rule: RateLimitOverride) -> Result<(), PolicyError> {
+        self.validate_override(&rule)?;
+
+        let mut overrides = self.overrides.write().unwrap_or_else(PoisonError::into_inner);
+        match overrides.iter_mut().find(|existing| existing.name == rule.name) {
+            Some(existing) => *existing = rule,
+            None => overrides.push(rule),
+        }
+        Ok(())
+    }
+
+    pub fn remove_override(&self, name: &str) -> bool {
+        let mut overrides = self.overrides.write().unwrap_or_else(PoisonError::into_inner);
+        let before = overrides.len();
+        overrides.retain(|rule| rule.name != name);
+        overrides.len() != before
+    }
+
+    pub fn evict_idle_local(&self) {
+        for policy in self.policies.values().chain(std::iter::once(&self.default)) {
+            for rule in &policy.rules {
+                rule.local.evict_idle();
+            }
+        }
+    }
+
+    pub fn header_options(&self) -> &HeaderOptions {
+        &self.headers
+    }
+
+    // Resolves every rule of the policy to the Redis key it counts against
+    pub fn evaluate<'a>(&'a self, policy: &'a CompiledPolicy, req: &ApiRequest) -> Vec<(String, &'a CompiledRule)> {
+        policy.rules.iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                let key = format!("{}{}:{}:{}", self.key_prefix, policy.name, i, rule.template.render(req));
+                (key, rule)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_template() {
+        let template = KeyTemplate::parse("admin.$remote_addr.$jwt_claim.sub.users").unwrap();
+        assert_eq!(template.segments, vec![
+            Segment::Literal("admin.".into()),
+            Segment::RemoteAddr,
+            Segment::Literal(".".into()),
+            Segment::JwtClaim("sub".into()),
+            Segment::Literal(".users".into()),
+        ]);
+
+        let template = KeyTemplate::parse("$http_x_client_id.products").unwrap();
+        assert_eq!(template.segments[0], Segment::Header("x-client-id".into()));
+
+        assert!(KeyTemplate::parse("$cookie_session").is_err());
+    }
+
+    #[test]
+    fn test_render_custom_claim() {
+        use crate::auth::jwt::Claims;
+        use hyper::Method;
+
+        let mut req = ApiRequest::for_test(Method::GET, "/products");
+        req.remote_addr = Some("8.8.8.8:4000".parse().unwrap());
+        let template = KeyTemplate::parse("tenant:$jwt_claim.tenant_id").unwrap();
+        assert_eq!(template.render(&req), "tenant:8.8.8.8");
+
+        let claims: Claims = serde_json::from_str(
+            r#"{"sub":"alice","exp":0,"iat":0,"iss":"idp","aud":"api","scope":"","tenant_id":"acme"}"#,
+        ).unwrap();
+        req.identity = Some(claims);
+        assert_eq!(template.render(&req), "tenant:acme");
+    }
+
+    #[test]
+    fn test_load_policies() {
+        let policies = RateLimitPolicies::from_yaml(r#"
+policies:
+  user_global:
+    type: ip
+    limit: 100
+    window: 1m
+    burst: 20
+  admin_strict:
+    type: composite
+    rules:
+      - type: ip
+        limit: 50
+        window: 1m
+      - type: jwt_claim
+        claim: sub
+        limit: 10
+        window: 30s
+defaults:
+  global:
+    limit: 60
+    window: 1m
+"#).unwrap();
+
+        assert_eq!(policies.policy(Some("user_global")).rules[0].config.burst, 20);
+        assert_eq!(policies.policy(Some("admin_strict")).rules.len(), 2);
+        assert_eq!(policies.policy(Some("admin_strict")).rules[1].config.refill_seconds, 30);
+        assert_eq!(policies.policy(Some("missing")).name, "default");
+        assert_eq!(policies.policy(Some("user_global")).on_store_failure, StoreFailureMode::Local);
+    }
+
+    #[test]
+    fn test_store_failure_mode() {
+        let policies = RateLimitPolicies::from_yaml(r#"
+strict_mode: true
+policies:
+  payments:
+    limit: 10
+    window: 1m
+  search:
+    limit: 100
+    window: 1m
+    on_store_failure: allow
+    local_first: true
+"#).unwrap();
+
+        assert_eq!(policies.policy(Some("payments")).on_store_failure, StoreFailureMode::Deny);
+        assert_eq!(policies.policy(Some("search")).on_store_failure, StoreFailureMode::Allow);
+        assert!(policies.policy(Some("search")).local_first);
+        assert_eq!(policies.policy(None).on_store_failure, StoreFailureMode::Deny);
+    }
+
+    #[test]
+    fn test_shadow_mode_and_overrides() {
+        use crate::auth::jwt::Claims;
+        use crate::rate_limiting::overrides::OverrideMatch;
+        use hyper::Method;
+
+        let policies = RateLimitPolicies::from_yaml(r#"
+policies:
+  search:
+    limit: 100
+    window: 1m
+    mode: shadow
+  partner_tier:
+    limit: 10000
+    window: 1m
+overrides:
+  - name: health_probes
+    match:
+      cidr: 10.0.0.0/8
+    action: bypass
+  - name: acme
+    match:
+      api_key: acme
+    action:
+      policy: partner_tier
+    policies: [search]
+"#).unwrap();
+        assert_eq!(policies.policy(Some("search")).mode, PolicyMode::Shadow);
+
+        let request = |ip: &str, consumer: Option<&str>| {
+            let mut req = ApiRequest::for_test(Method::GET, "/search");
+            req.remote_addr = Some(format!("{}:4000", ip).parse().unwrap());
+            req.identity = consumer.map(|sub| Claims {
+                sub: sub.into(),
+                iss: "gateway-api-key".into(),
+                ..Claims::anonymous()
+            });
+            req
+        };
+
+        assert!(policies.resolve(Some("search"), &request("10.1.2.3", None)).is_none());
+        assert_eq!(policies.resolve(Some("search"), &request("8.8.8.8", Some("acme"))).unwrap().name, "partner_tier");
+        assert_eq!(policies.resolve(None, &request("8.8.8.8", Some("acme"))).unwrap().name, "default");
+
+        // Runtime edits
+        assert!(policies.remove_override("health_probes"));
+        assert_eq!(policies.resolve(Some("search"), &request("10.1.2.3", None)).unwrap().name, "search");
+        assert!(policies.upsert_override(RateLimitOverride {
+            name: "broken".into(),
+            matcher: OverrideMatch::ApiKey("acme".into()),
+            action: OverrideAction::Policy("missing".into()),
+            policies: vec![],
+        }).is_err());
+        assert_eq!(policies.overrides().len(), 1);
+    }
+
+    #[test]
+    fn test_algorithm_selection() {
+        let policies = RateLimitPolicies::from_yaml(r#"
+policies:
+  login:
+    limit: 5
+    window: 1m
+    algorithm: sliding_window_log
+  search:
+    limit: 30
+    window: 10s
+    algorithm: sliding_window_counter
+  uploads:
+    limit: 10
+    window: 1m
+    algorithm: gcra
+"#).unwrap();
+
+        assert_eq!(policies.policy(Some("login")).rules[0].algorithm, RateLimitAlgorithm::SlidingWindowLog);
+        assert_eq!(policies.policy(Some("search")).rules[0].algorithm, RateLimitAlgorithm::SlidingWindow);
+        assert_eq!(policies.policy(Some("uploads")).rules[0].algorithm, RateLimitAlgorithm::Gcra);
+
+        assert!(RateLimitPolicies::from_yaml(r#"
+policies:
+  mixed:
+    type: composite
+    rules:
+      - limit: 5
+        window: 1m
+        algorithm: fixed_window
+"#).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+-- Long-period quota counters, one key per calendar period of a plan.
+-- Nothing is counted unless every period still has room.
+-- ARGV holds (limit, expire_at) per key.
+for i, key in ipairs(KEYS) do
+    local limit = tonumber(ARGV[(i - 1) * 2 + 1])
+    local used = tonumber(redis.call('GET', key) or 0)
+    if used >= limit then
+        return { 0, i, 0 }
+    end
+end
+
+local tightest = 0
+local lowest = -1
+for i, key in ipairs(KEYS) do
+    local limit = tonumber(ARGV[(i - 1) * 2 + 1])
+    local used = redis.call('INCR', key)
+    if used == 1 then
+        redis.call('EXPIREAT', key, ARGV[(i - 1) * 2 + 2])
+    end
+
+    local remaining = limit - used
+    if lowest < 0 or remaining < lowest then
+        lowest = remaining
+        tightest = i
+    end
+end
+
+return { 1, tightest, lowest }
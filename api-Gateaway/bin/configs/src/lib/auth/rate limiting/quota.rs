@@ -0,0 +1,424 @@
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Utc};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use hyper::StatusCode;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use crate::rate_limiting::redis_store::RedisConnection;
+
+const X_QUOTA_REMAINING: HeaderName = HeaderName::from_static("x-quota-remaining");
+// How often plans and assignments edited on other instances are picked up
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum QuotaError {
+    #[error("Cannot read quota plans: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid quota plans: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("Consumer `{0}` is assigned to unknown plan `{1}`")]
+    UnknownPlan(String, String),
+    #[error("Redis error: {0}")]
+    RedisError(#[from] redis::RedisError),
+    #[error("Invalid stored quota plan: {0}")]
+    Encoding(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaPeriod {
+    Daily,
+    Monthly,
+}
+
+impl QuotaPeriod {
+    // Calendar bucket (UTC) containing `now` and the timestamp it ends at
+    fn window(&self, now: DateTime<Utc>) -> (String, i64) {
+        let today = now.date_naive();
+        match self {
+            QuotaPeriod::Daily => {
+                let tomorrow = today.succ_opt().unwrap_or(today);
+                (today.format("d%Y%m%d").to_string(), midnight(tomorrow))
+            }
+            QuotaPeriod::Monthly => {
+                let (year, month) = match today.month() {
+                    12 => (today.year() + 1, 1),
+                    month => (today.year(), month + 1),
+                };
+                let next_month = NaiveDate::from_ymd_opt(year, month, 1).unwrap_or(today);
+                (today.format("m%Y%m").to_string(), midnight(next_month))
+            }
+        }
+    }
+}
+
+fn midnight(date: NaiveDate) -> i64 {
+    Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN)).timestamp()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaExceededResponse {
+    #[default]
+    TooManyRequests,
+    PaymentRequired,
+}
+
+impl QuotaExceededResponse {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            QuotaExceededResponse::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            QuotaExceededResponse::PaymentRequired => StatusCode::PAYMENT_REQUIRED,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaPlan {
+    pub daily: Option<u64>,
+    pub monthly: Option<u64>,
+    #[serde(default)]
+    pub on_exceeded: QuotaExceededResponse,
+}
+
+impl QuotaPlan {
+    fn limits(&self) -> Vec<(QuotaPeriod, u64)> {
+        [(QuotaPeriod::Daily, self.daily), (QuotaPeriod::Monthly, self.monthly)]
+            .into_iter()
+            .filter_map(|(period, limit)| Some((period, limit?)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuotaFile {
+    #[serde(default)]
+    pub plans: HashMap<String, QuotaPlan>,
+    // Consumer (authenticated subject) -> plan
+    #[serde(default)]
+    pub consumers: HashMap<String, String>,
+    pub default_plan: Option<String>,
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+    // Let requests through unmetered while Redis is unreachable instead of rejecting them
+    #[serde(default)]
+    pub fail_open: bool,
+}
+
+fn default_key_prefix() -> String {
+    "quota:".into()
+}
+
+#[derive(Debug, Clone)]
+pub struct QuotaPolicies {
+    file: QuotaFile,
+}
+
+impl QuotaPolicies {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, QuotaError> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_yaml(&contents)
+    }
+
+    pub fn from_yaml(contents: &str) -> Result<Self, QuotaError> {
+        let file: QuotaFile = serde_yaml::from_str(contents)?;
+
+        let assignments = file.consumers.iter()
+            .map(|(consumer, plan)| (consumer.as_str(), plan))
+            .chain(file.default_plan.iter().map(|plan| ("*", plan)));
+        for (consumer, plan) in assignments {
+            if !file.plans.contains_key(plan) {
+                return Err(QuotaError::UnknownPlan(consumer.into(), plan.clone()));
+            }
+        }
+
+        Ok(Self { file })
+    }
+
+    pub fn plan_for(&self, consumer: &str) -> Option<(&str, &QuotaPlan)> {
+        let name = self.file.consumers.get(consumer).or(self.file.default_plan.as_ref())?;
+        self.file.plans.get(name).map(|plan| (name.as_str(), plan))
+    }
+}
+
+// Quota state of the tightest period after a request was counted
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaStatus {
+    pub allowed: bool,
+    pub period: QuotaPeriod,
+    pub limit: u64,
+    pub remaining: u64,
+    // Seconds until the period resets
+    pub reset: u64,
+    pub on_exceeded: QuotaExceededResponse,
+}
+
+impl QuotaStatus {
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        headers.insert(X_QUOTA_REMAINING, HeaderValue::from(self.remaining));
+        if !self.allowed {
+            headers.insert(RETRY_AFTER, HeaderValue::from(self.reset.max(1)));
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PeriodUsage {
+    pub period: QuotaPeriod,
+    pub used: u64,
+    pub limit: u64,
+    pub resets_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuotaUsage {
+    pub consumer: String,
+    pub plan: String,
+    pub periods: Vec<PeriodUsage>,
+}
+
+// Plans and consumer assignments are stored in Redis and shared by every
+// instance; the YAML file only seeds a store that holds no plans yet.
+pub struct QuotaManager {
+    // Last copy read from Redis, the seed until then
+    policies: RwLock<QuotaPolicies>,
+    key_prefix: String,
+    fail_open: bool,
+    conn: RedisConnection,
+    script: redis::Script,
+}
+
+impl QuotaManager {
+    pub fn new(policies: QuotaPolicies, conn: RedisConnection) -> Self {
+        Self {
+            key_prefix: policies.file.key_prefix.clone(),
+            fail_open: policies.file.fail_open,
+            policies: RwLock::new(policies),
+            conn,
+            script: redis::Script::new(include_str!("quota.lua")),
+        }
+    }
+
+    pub fn fails_open(&self) -> bool {
+        self.fail_open
+    }
+
+    fn key(&self, consumer: &str, bucket: &str) -> String {
+        format!("{}{}:{}", self.key_prefix, consumer, bucket)
+    }
+
+    fn config_key(&self, name: &str) -> String {
+        format!("{}config:{}", self.key_prefix, name)
+    }
+
+    fn plan_for(&self, consumer: &str) -> Option<(String, QuotaPlan)> {
+        let policies = self.policies.read().unwrap_or_else(PoisonError::into_inner);
+        policies.plan_for(consumer).map(|(name, plan)| (name.to_string(), plan.clone()))
+    }
+
+    // Reloads plans and assignments from Redis, seeding it on first use
+    pub async fn sync(&self) -> Result<(), QuotaError> {
+        let (plans_key, consumers_key, default_key) =
+            (self.config_key("plans"), self.config_key("consumers"), self.config_key("default_plan"));
+        let mut conn = self.conn.clone();
+
+        let seeded: bool = conn.exists(&plans_key).await?;
+        if !seeded {
+            let seed = self.policies.read().unwrap_or_else(PoisonError::into_inner).file.clone();
+            if !seed.plans.is_empty() {
+                let mut pipe = redis::pipe();
+                pipe.atomic();
+                for (name, plan) in &seed.plans {
+                    pipe.hset_nx(&plans_key, name, serde_json::to_string(plan)?).ignore();
+                }
+                for (consumer, plan) in &seed.consumers {
+                    pipe.hset_nx(&consumers_key, consumer, plan).ignore();
+                }
+                if let Some(default_plan) = &seed.default_plan {
+                    pipe.set_nx(&default_key, default_plan).ignore();
+                }
+                let _: () = pipe.query_async(&mut conn).await?;
+            }
+        }
+
+        let plans: HashMap<String, String> = conn.hgetall(&plans_key).await?;
+        let consumers: HashMap<String, String> = conn.hgetall(&consumers_key).await?;
+        let default_plan: Option<String> = conn.get(&default_key).await?;
+        let plans = plans.into_iter()
+            .filter_map(|(name, plan)| match serde_json::from_str(&plan) {
+                Ok(plan) => Some((name, plan)),
+                Err(e) => {
+                    log::warn!("Ignoring invalid quota plan {}: {}", name, e);
+                    None
+                }
+            })
+            .collect();
+
+        let mut policies = self.policies.write().unwrap_or_else(PoisonError::into_inner);
+        policies.file.plans = plans;
+        policies.file.consumers = consumers;
+        policies.file.default_plan = default_plan;
+        Ok(())
+    }
+
+    pub async fn keep_synced(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.sync().await {
+                log::warn!("Cannot load quota plans from Redis, keeping the current ones: {}", e);
+            }
+        }
+    }
+
+    // Creates or replaces a plan on every instance
+    pub async fn put_plan(&self, name: &str, plan: QuotaPlan) -> Result<(), QuotaError> {
+        let mut conn = self.conn.clone();
+        let _: () = conn.hset(self.config_key("plans"), name, serde_json::to_string(&plan)?).await?;
+        self.policies.write().unwrap_or_else(PoisonError::into_inner).file.plans.insert(name.to_string(), plan);
+        Ok(())
+    }
+
+    // Moves a consumer to another plan, or back to the default one with None
+    pub async fn assign(&self, consumer: &str, plan: Option<&str>) -> Result<(), QuotaError> {
+        let mut conn = self.conn.clone();
+        match plan {
+            Some(plan) => {
+                if !self.policies.read().unwrap_or_else(PoisonError::into_inner).file.plans.contains_key(plan) {
+                    return Err(QuotaError::UnknownPlan(consumer.into(), plan.into()));
+                }
+                let _: () = conn.hset(self.config_key("consumers"), consumer, plan).await?;
+            }
+            None => {
+                let _: () = conn.hdel(self.config_key("consumers"), consumer).await?;
+            }
+        }
+
+        let mut policies = self.policies.write().unwrap_or_else(PoisonError::into_inner);
+        match plan {
+            Some(plan) => policies.file.consumers.insert(consumer.to_string(), plan.to_string()),
+            None => policies.file.consumers.remove(consumer),
+        };
+        Ok(())
+    }
+
+    // Counts one request; None when the consumer has no plan
+    pub async fn check(&self, consumer: &str) -> Result<Option<QuotaStatus>, QuotaError> {
+        let Some((_, plan)) = self.plan_for(consumer) else {
+            return Ok(None);
+        };
+
+        let limits = plan.limits();
+        if limits.is_empty() {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        let mut windows = Vec::with_capacity(limits.len());
+        let mut invocation = self.script.prepare_invoke();
+        for (period, limit) in &limits {
+            let (bucket, ends_at) = period.window(now);
+            invocation.key(self.key(consumer, &bucket)).arg(*limit).arg(ends_at);
+            windows.push(ends_at);
+        }
+
+        let mut conn = self.conn.clone();
+        let (allowed, index, remaining): (i64, usize, i64) = invocation.invoke_async(&mut conn).await?;
+
+        // The script reports periods 1-based
+        let index = index.saturating_sub(1).min(limits.len() - 1);
+        let (period, limit) = limits[index];
+
+        Ok(Some(QuotaStatus {
+            allowed: allowed == 1,
+            period,
+            limit,
+            remaining: remaining.max(0) as u64,
+            reset: (windows[index] - now.timestamp()).max(0) as u64,
+            on_exceeded: plan.on_exceeded,
+        }))
+    }
+
+    pub async fn usage(&self, consumer: &str) -> Result<Option<QuotaUsage>, QuotaError> {
+        let Some((plan_name, plan)) = self.plan_for(consumer) else {
+            return Ok(None);
+        };
+
+        let now = Utc::now();
+        let mut conn = self.conn.clone();
+        let mut periods = Vec::new();
+        for (period, limit) in plan.limits() {
+            let (bucket, resets_at) = period.window(now);
+            let used: Option<u64> = conn.get(self.key(consumer, &bucket)).await?;
+            periods.push(PeriodUsage {
+                period,
+                used: used.unwrap_or_default(),
+                limit,
+                resets_at,
+            });
+        }
+
+        Ok(Some(QuotaUsage {
+            consumer: consumer.to_string(),
+            plan: plan_name,
+            periods,
+        }))
+    }
+
+    // Clears the consumer's usage for the current periods
+    pub async fn reset(&self, consumer: &str) -> Result<(), QuotaError> {
+        let now = Utc::now();
+        let keys: Vec<_> = [QuotaPeriod::Daily, QuotaPeriod::Monthly]
+            .iter()
+            .map(|period| self.key(consumer, &period.window(now).0))
+            .collect();
+
+        let mut conn = self.conn.clone();
+        let _: () = conn.del(keys).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar_windows() {
+        let now = Utc.with_ymd_and_hms(2024, 12, 31, 18, 30, 0).unwrap();
+
+        let (bucket, ends_at) = QuotaPeriod::Daily.window(now);
+        assert_eq!(bucket, "d20241231");
+        assert_eq!(ends_at, Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap().timestamp());
+
+        let (bucket, ends_at) = QuotaPeriod::Monthly.window(now);
+        assert_eq!(bucket, "m202412");
+        assert_eq!(ends_at, Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap().timestamp());
+    }
+
+    #[test]
+    fn test_plan_assignment() {
+        let policies = QuotaPolicies::from_yaml(r#"
+plans:
+  free:
+    daily: 1000
+  pro:
+    monthly: 100000
+    on_exceeded: payment_required
+consumers:
+  acme: pro
+default_plan: free
+"#).unwrap();
+
+        let (name, plan) = policies.plan_for("acme").unwrap();
+        assert_eq!(name, "pro");
+        assert_eq!(plan.on_exceeded.status(), StatusCode::PAYMENT_REQUIRED);
+        assert_eq!(policies.plan_for("someone").unwrap().0, "free");
+
+        assert!(QuotaPolicies::from_yaml("plans: {}\ndefault_plan: gold\n").is_err());
+    }
+}
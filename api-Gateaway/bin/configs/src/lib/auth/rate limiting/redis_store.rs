@@ -0,0 +1,335 @@
+use redis::{Client, Cmd, FromRedisValue, Pipeline, RedisFuture, RedisResult, ScriptInvocation, Value, cmd};
+use redis::aio::{ConnectionLike, ConnectionManager};
+use redis::AsyncCommands;
+use thiserror::Error;
+use serde::{Serialize, Deserialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use crate::models::config::RateLimitAlgorithm;
+
+#[derive(Debug, Error)]
+pub enum RedisRateLimitError {
+    #[error("Redis error: {0}")]
+    RedisError(#[from] redis::RedisError),
+    #[error("Rate limit exceeded for key {0}")]
+    RateLimitExceeded(String),
+}
+
+const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Connects on first use, and again on the next call after a failed attempt, so
+// the gateway starts while Redis is down. Until it is reachable callers get
+// ordinary Redis errors and rate limit policies follow their `on_store_failure`.
+// Every command is bounded by `timeout`, so a hung Redis fails the same way.
+#[derive(Clone)]
+pub struct RedisConnection {
+    client: Client,
+    conn: Arc<OnceCell<ConnectionManager>>,
+    timeout: Duration,
+}
+
+impl RedisConnection {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            conn: Arc::default(),
+            timeout: DEFAULT_COMMAND_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn manager(&self) -> RedisResult<ConnectionManager> {
+        self.conn
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .cloned()
+    }
+
+    // Covers connecting as well as the command itself
+    async fn bounded<T>(&self, request: impl std::future::Future<Output = RedisResult<T>>) -> RedisResult<T> {
+        tokio::time::timeout(self.timeout, request)
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "Redis did not answer in time"))?
+    }
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            self.bounded(async {
+                let mut conn = self.manager().await?;
+                conn.req_packed_command(cmd).await
+            })
+            .await
+        })
+    }
+
+    fn req_packed_commands<'a>(&'a mut self, cmd: &'a Pipeline, offset: usize, count: usize) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            self.bounded(async {
+                let mut conn = self.manager().await?;
+                conn.req_packed_commands(cmd, offset, count).await
+            })
+            .await
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.client.get_connection_info().redis.db
+    }
+}
+
+#[derive(Clone)]
+pub struct RedisRateLimiter {
+    // Multiplexed and reconnecting, cheap to clone per request
+    conn: RedisConnection,
+    lua_script: redis::Script,
+    composite_script: redis::Script,
+    fixed_window_script: redis::Script,
+    sliding_log_script: redis::Script,
+    sliding_counter_script: redis::Script,
+    gcra_script: redis::Script,
+    lease_script: redis::Script,
+    lease_renew_script: redis::Script,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    pub capacity: i64,
+    pub refill_amount: i64,
+    pub refill_seconds: u64,
+    pub burst: i64,
+}
+
+impl RedisRateLimiter {
+    pub fn new(redis_url: &str) -> RedisResult<Self> {
+        let conn = RedisConnection::new(Client::open(redis_url)?);
+        let lua_script = redis::Script::new(include_str!("token_bucket.lua"));
+        let composite_script = redis::Script::new(include_str!("composite_bucket.lua"));
+        
+        Ok(Self {
+            conn,
+            lua_script,
+            composite_script,
+            fixed_window_script: redis::Script::new(include_str!("fixed_window.lua")),
+            sliding_log_script: redis::Script::new(include_str!("sliding_window_log.lua")),
+            sliding_counter_script: redis::Script::new(include_str!("sliding_window_counter.lua")),
+            gcra_script: redis::Script::new(include_str!("gcra.lua")),
+            lease_script: redis::Script::new(include_str!("concurrency_lease.lua")),
+            lease_renew_script: redis::Script::new(include_str!("concurrency_lease_renew.lua")),
+        })
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.conn = self.conn.with_timeout(timeout);
+        self
+    }
+
+    // Shares the multiplexed connection with other Redis-backed features
+    pub fn connection(&self) -> RedisConnection {
+        self.conn.clone()
+    }
+
+    pub async fn check_rate_limit(
+        &self,
+        key: &str,
+        config: &RateLimitConfig,
+    ) -> Result<(bool, i64), RedisRateLimitError> {
+        let (allowed, remaining, _) = self.check(key, config, &RateLimitAlgorithm::TokenBucket).await?;
+        Ok((allowed, remaining))
+    }
+
+    // Returns (allowed, remaining, seconds until the limit resets) for any algorithm.
+    // Window algorithms use `capacity` per `refill_seconds`; only the token bucket and
+    // GCRA take `burst` into account.
+    pub async fn check(
+        &self,
+        key: &str,
+        config: &RateLimitConfig,
+        algorithm: &RateLimitAlgorithm,
+    ) -> Result<(bool, i64, u64), RedisRateLimitError> {
+        self.check_n(key, config, algorithm, 1).await
+    }
+
+    // Same as `check` for a request that consumes `cost` units of the limit
+    pub async fn check_n(
+        &self,
+        key: &str,
+        config: &RateLimitConfig,
+        algorithm: &RateLimitAlgorithm,
+        cost: u32,
+    ) -> Result<(bool, i64, u64), RedisRateLimitError> {
+        self.run(key, config, algorithm, cost, false).await
+    }
+
+    // Charges `cost` after the fact, overdrawing the limit if needed so that
+    // following requests pay for it
+    pub async fn debit(
+        &self,
+        key: &str,
+        config: &RateLimitConfig,
+        algorithm: &RateLimitAlgorithm,
+        cost: u32,
+    ) -> Result<(bool, i64, u64), RedisRateLimitError> {
+        self.run(key, config, algorithm, cost, true).await
+    }
+
+    async fn run(
+        &self,
+        key: &str,
+        config: &RateLimitConfig,
+        algorithm: &RateLimitAlgorithm,
+        cost: u32,
+        force: bool,
+    ) -> Result<(bool, i64, u64), RedisRateLimitError> {
+        let force = force as u8;
+        let invocation = match algorithm {
+            RateLimitAlgorithm::TokenBucket => {
+                let mut invocation = self.lua_script.prepare_invoke();
+                invocation
+                    .key(key)
+                    .arg(config.capacity)
+                    .arg(config.refill_amount)
+                    .arg(config.refill_seconds)
+                    .arg(config.burst)
+                    .arg(cost)
+                    .arg(force);
+                invocation
+            }
+            RateLimitAlgorithm::FixedWindow => {
+                let mut invocation = self.fixed_window_script.prepare_invoke();
+                invocation.key(key).arg(config.capacity).arg(config.refill_seconds).arg(cost).arg(force);
+                invocation
+            }
+            RateLimitAlgorithm::SlidingWindow => {
+                let mut invocation = self.sliding_counter_script.prepare_invoke();
+                invocation.key(key).arg(config.capacity).arg(config.refill_seconds).arg(cost).arg(force);
+                invocation
+            }
+            RateLimitAlgorithm::SlidingWindowLog => {
+                let mut invocation = self.sliding_log_script.prepare_invoke();
+                invocation
+                    .key(key)
+                    .arg(config.capacity)
+                    .arg(config.refill_seconds)
+                    // Sorted-set members must be unique even for requests in the same millisecond
+                    .arg(uuid::Uuid::new_v4().to_string())
+                    .arg(cost)
+                    .arg(force);
+                invocation
+            }
+            RateLimitAlgorithm::Gcra => {
+                let mut invocation = self.gcra_script.prepare_invoke();
+                invocation
+                    .key(key)
+                    .arg(config.capacity)
+                    .arg(config.refill_seconds)
+                    .arg(config.burst)
+                    .arg(cost)
+                    .arg(force);
+                invocation
+            }
+        };
+
+        let result: (i64, i64, i64) = self.invoke(&invocation).await?;
+
+        Ok((result.0 == 1, result.1, result.2.max(0) as u64))
+    }
+
+    // All limits are charged together or not at all; `remaining` is the tightest one
+    pub async fn check_composite_rate_limit(
+        &self,
+        limits: &[(String, RateLimitConfig)],
+        cost: u32,
+    ) -> Result<(bool, i64, u64), RedisRateLimitError> {
+        self.run_composite(limits, cost, false).await
+    }
+
+    pub async fn debit_composite(
+        &self,
+        limits: &[(String, RateLimitConfig)],
+        cost: u32,
+    ) -> Result<(bool, i64, u64), RedisRateLimitError> {
+        self.run_composite(limits, cost, true).await
+    }
+
+    async fn run_composite(
+        &self,
+        limits: &[(String, RateLimitConfig)],
+        cost: u32,
+        force: bool,
+    ) -> Result<(bool, i64, u64), RedisRateLimitError> {
+        let mut invocation = self.composite_script.prepare_invoke();
+        for (key, config) in limits {
+            invocation
+                .key(key)
+                .arg(config.capacity)
+                .arg(config.refill_amount)
+                .arg(config.refill_seconds)
+                .arg(config.burst);
+        }
+        invocation.arg(cost).arg(force as u8);
+
+        let result: (i64, i64, i64) = self.invoke(&invocation).await?;
+
+        Ok((result.0 == 1, result.1, result.2.max(0) as u64))
+    }
+
+    // The connection's timeout keeps a hung Redis from stalling the request path
+    async fn invoke<T: FromRedisValue>(&self, invocation: &ScriptInvocation<'_>) -> Result<T, RedisRateLimitError> {
+        let mut conn = self.conn.clone();
+        Ok(invocation.invoke_async(&mut conn).await?)
+    }
+
+    // Takes one of `max_in_flight` slots shared by all instances; returns whether it was granted
+    pub async fn acquire_lease(
+        &self,
+        key: &str,
+        lease_id: &str,
+        max_in_flight: u32,
+        ttl: Duration,
+    ) -> Result<bool, RedisRateLimitError> {
+        let mut invocation = self.lease_script.prepare_invoke();
+        invocation
+            .key(key)
+            .arg(max_in_flight)
+            .arg(ttl.as_millis() as u64)
+            .arg(lease_id);
+
+        let (granted, _): (i64, i64) = self.invoke(&invocation).await?;
+        Ok(granted == 1)
+    }
+
+    // Extends a held lease by `ttl`; false when it has already expired
+    pub async fn renew_lease(&self, key: &str, lease_id: &str, ttl: Duration) -> Result<bool, RedisRateLimitError> {
+        let mut invocation = self.lease_renew_script.prepare_invoke();
+        invocation.key(key).arg(ttl.as_millis() as u64).arg(lease_id);
+
+        let renewed: i64 = self.invoke(&invocation).await?;
+        Ok(renewed == 1)
+    }
+
+    pub async fn release_lease(&self, key: &str, lease_id: &str) -> RedisResult<()> {
+        let mut conn = self.conn.clone();
+        conn.zrem(key, lease_id).await
+    }
+
+    pub async fn get_remaining(&self, key: &str) -> RedisResult<i64> {
+        let mut conn = self.conn.clone();
+        let remaining: i64 = conn.get(format!("{key}:remaining")).await?;
+        Ok(remaining)
+    }
+
+    pub async fn reset_rate_limit(&self, key: &str) -> RedisResult<()> {
+        let mut conn = self.conn.clone();
+        cmd("DEL")
+            .arg(&[format!("{key}:tokens"), format!("{key}:last_refill")])
+            .query_async(&mut conn)
+            .await
+    }
+}
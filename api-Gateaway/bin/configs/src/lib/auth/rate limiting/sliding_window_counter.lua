@@ -0,0 +1,29 @@
+-- Approximated sliding window: the previous window's count is weighted
+-- by how much of it still overlaps the sliding window.
+-- ARGV: limit, window_seconds, cost, force
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local cost = tonumber(ARGV[3] or 1)
+local force = ARGV[4] == '1'
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) + tonumber(time[2]) / 1000000
+local current_start = math.floor(now / window) * window
+local elapsed = now - current_start
+
+local current_key = KEYS[1]..':'..current_start
+local previous_key = KEYS[1]..':'..(current_start - window)
+local current = tonumber(redis.call('GET', current_key) or 0)
+local previous = tonumber(redis.call('GET', previous_key) or 0)
+
+local weighted = previous * ((window - elapsed) / window) + current
+local reset = math.ceil(window - elapsed)
+
+if not force and weighted + cost > limit then
+    return { 0, math.max(0, math.floor(limit - weighted)), reset }
+end
+
+redis.call('INCRBY', current_key, cost)
+redis.call('EXPIRE', current_key, window * 2)
+
+return { 1, math.max(0, math.floor(limit - weighted - cost)), reset }
@@ -0,0 +1,44 @@
+-- Exact sliding window: one sorted-set member per accepted request, named
+-- "<id>:<cost>" so a request of any cost is a single entry. The summed cost
+-- of the members still in the window is kept under key:weight.
+-- ARGV: limit, window_seconds, unique member id, cost, force
+local key = KEYS[1]
+local weight_key = key..':weight'
+local limit = tonumber(ARGV[1])
+local window_ms = tonumber(ARGV[2]) * 1000
+local member = ARGV[3]
+local cost = tonumber(ARGV[4] or 1)
+local force = ARGV[5] == '1'
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local count = tonumber(redis.call('GET', weight_key) or 0)
+local expired = redis.call('ZRANGEBYSCORE', key, '-inf', now - window_ms)
+for _, entry in ipairs(expired) do
+    count = count - tonumber(string.match(entry, ':(%d+)$') or 1)
+end
+if #expired > 0 then
+    redis.call('ZREMRANGEBYSCORE', key, '-inf', now - window_ms)
+end
+if redis.call('ZCARD', key) == 0 then
+    count = 0
+end
+
+local allowed = 0
+if force or count + cost <= limit then
+    redis.call('ZADD', key, now, member..':'..cost)
+    count = count + cost
+    allowed = 1
+end
+redis.call('SET', weight_key, math.max(0, count), 'PX', window_ms)
+redis.call('PEXPIRE', key, window_ms)
+
+-- Capacity frees up when the oldest request leaves the window
+local reset = 0
+local oldest = redis.call('ZRANGE', key, 0, 0, 'WITHSCORES')
+if oldest[2] then
+    reset = math.ceil((tonumber(oldest[2]) + window_ms - now) / 1000)
+end
+
+return { allowed, math.max(0, limit - count), reset }
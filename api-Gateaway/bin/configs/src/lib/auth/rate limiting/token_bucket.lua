@@ -0,0 +1,35 @@
+local key = KEYS[1]
+local capacity = tonumber(ARGV[1])
+local refill_amount = tonumber(ARGV[2])
+local refill_seconds = tonumber(ARGV[3])
+local burst = tonumber(ARGV[4])
+local cost = tonumber(ARGV[5] or 1)
+-- Debits charge the cost even when it overdraws the bucket
+local force = ARGV[6] == '1'
+
+local current_time = tonumber(redis.call('TIME')[1])
+local last_refill = tonumber(redis.call('GET', key..':last_refill') or current_time)
+local tokens = tonumber(redis.call('GET', key..':tokens') or capacity)
+
+local time_passed = current_time - last_refill
+if time_passed > 0 then
+    local refill_count = math.floor(time_passed / refill_seconds) * refill_amount
+    tokens = math.min(tokens + refill_count, capacity + burst)
+    last_refill = current_time - (time_passed % refill_seconds)
+end
+
+local allowed = 0
+if force or tokens >= cost then
+    tokens = tokens - cost
+    allowed = 1
+end
+
+redis.call('SET', key..':tokens', tokens)
+redis.call('SET', key..':last_refill', last_refill)
+redis.call('EXPIRE', key..':tokens', refill_seconds * 2)
+redis.call('EXPIRE', key..':last_refill', refill_seconds * 2)
+
+-- Seconds until the next refill
+local reset = refill_seconds - (current_time - last_refill)
+
+return { allowed, math.max(0, tokens), reset }
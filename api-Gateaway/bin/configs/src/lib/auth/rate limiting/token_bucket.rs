@@ -0,0 +1,334 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+const SHARDS: usize = 16;
+// Buckets inspected for an idle one when a shard is at capacity
+const EVICTION_SAMPLE: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum RateLimitError {
+    #[error("Rate limit exceeded")]
+    RateLimitExceeded,
+    #[error("Rate limit exceeded, retry in {0:?}")]
+    RetryAfter(Duration),
+    #[error("Requested {0} tokens, more than the bucket can ever hold")]
+    ExceedsCapacity(u64),
+    #[error("Invalid rate limit configuration")]
+    InvalidConfig,
+}
+
+// Refill rate shared by every bucket of a limiter
+#[derive(Debug, Clone, Copy)]
+struct Rate {
+    capacity: f64,
+    tokens_per_nano: f64,
+}
+
+impl Rate {
+    fn new(capacity: u64, refill_amount: u64, refill_interval: Duration) -> Result<Self, RateLimitError> {
+        if capacity == 0 || refill_amount == 0 || refill_interval.is_zero() {
+            return Err(RateLimitError::InvalidConfig);
+        }
+
+        Ok(Self {
+            capacity: capacity as f64,
+            tokens_per_nano: refill_amount as f64 / refill_interval.as_nanos() as f64,
+        })
+    }
+
+    fn wait_for(&self, missing: f64) -> Duration {
+        Duration::from_nanos((missing / self.tokens_per_nano).ceil() as u64)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl BucketState {
+    fn full(rate: &Rate, now: Instant) -> Self {
+        Self {
+            tokens: rate.capacity,
+            last_refill: now,
+        }
+    }
+
+    // Tokens and the refill timestamp are always updated together under the same lock
+    fn refill(&mut self, rate: &Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_nanos() as f64;
+        self.tokens = (self.tokens + elapsed * rate.tokens_per_nano).min(rate.capacity);
+        self.last_refill = now;
+    }
+
+    fn try_acquire(&mut self, rate: &Rate, n: u64, now: Instant) -> Result<u64, RateLimitError> {
+        if n as f64 > rate.capacity {
+            return Err(RateLimitError::ExceedsCapacity(n));
+        }
+
+        self.refill(rate, now);
+        let n = n as f64;
+        if self.tokens < n {
+            return Err(RateLimitError::RetryAfter(rate.wait_for(n - self.tokens)));
+        }
+
+        self.tokens -= n;
+        Ok(self.tokens.floor() as u64)
+    }
+
+    // Time until the bucket is full again, after which it carries no state
+    fn idle_for(&self, rate: &Rate, now: Instant) -> Option<Duration> {
+        let full_at = self.last_refill + rate.wait_for(rate.capacity - self.tokens);
+        now.checked_duration_since(full_at)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub struct TokenBucket {
+    rate: Rate,
+    state: Mutex<BucketState>,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u64, refill_amount: u64, refill_interval: Duration) -> Result<Self, RateLimitError> {
+        let rate = Rate::new(capacity, refill_amount, refill_interval)?;
+
+        Ok(Self {
+            rate,
+            state: Mutex::new(BucketState::full(&rate, Instant::now())),
+        })
+    }
+
+    pub fn available(&self) -> u64 {
+        let mut state = lock(&self.state);
+        state.refill(&self.rate, Instant::now());
+        state.tokens.floor() as u64
+    }
+
+    pub fn try_acquire(&self, tokens: u64) -> Result<(), RateLimitError> {
+        self.try_acquire_n(tokens).map(|_| ()).map_err(|_| RateLimitError::RateLimitExceeded)
+    }
+
+    // Returns the tokens left, or how long to wait until `n` tokens are available
+    pub fn try_acquire_n(&self, n: u64) -> Result<u64, RateLimitError> {
+        lock(&self.state).try_acquire(&self.rate, n, Instant::now())
+    }
+}
+
+// Per-key token buckets sharing one rate, for limiting without Redis.
+// Keys are spread over independently locked shards, and a key whose bucket
+// has refilled completely is dropped since it is indistinguishable from a new one.
+#[derive(Debug)]
+pub struct KeyedTokenBucket {
+    rate: Rate,
+    shards: Vec<Mutex<HashMap<String, BucketState>>>,
+    max_keys_per_shard: usize,
+}
+
+impl KeyedTokenBucket {
+    pub fn new(capacity: u64, refill_amount: u64, refill_interval: Duration) -> Result<Self, RateLimitError> {
+        Ok(Self {
+            rate: Rate::new(capacity, refill_amount, refill_interval)?,
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            max_keys_per_shard: 100_000 / SHARDS,
+        })
+    }
+
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys_per_shard = (max_keys / SHARDS).max(1);
+        self
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<HashMap<String, BucketState>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    pub fn try_acquire_n(&self, key: &str, n: u64) -> Result<u64, RateLimitError> {
+        let now = Instant::now();
+        let mut shard = lock(self.shard(key));
+
+        if let Some(state) = shard.get_mut(key) {
+            return state.try_acquire(&self.rate, n, now);
+        }
+
+        let mut state = BucketState::full(&self.rate, now);
+        let result = state.try_acquire(&self.rate, n, now);
+        self.track(&mut shard, key, state, now);
+        result
+    }
+
+    // Puts back tokens taken for a request that was not let through after all
+    pub fn release(&self, key: &str, n: u64) {
+        if let Some(state) = lock(self.shard(key)).get_mut(key) {
+            state.tokens = (state.tokens + n as f64).min(self.rate.capacity);
+        }
+    }
+
+    // Charges a cost only known after the request ran; the bucket may go
+    // negative, which delays the key's next requests until it is paid off
+    pub fn debit(&self, key: &str, n: u64) {
+        let now = Instant::now();
+        let mut shard = lock(self.shard(key));
+        match shard.get_mut(key) {
+            Some(state) => {
+                state.refill(&self.rate, now);
+                state.tokens -= n as f64;
+            }
+            None => {
+                let mut state = BucketState::full(&self.rate, now);
+                state.tokens -= n as f64;
+                self.track(&mut shard, key, state, now);
+            }
+        }
+    }
+
+    pub fn available(&self, key: &str) -> u64 {
+        let now = Instant::now();
+        match lock(self.shard(key)).get_mut(key) {
+            Some(state) => {
+                state.refill(&self.rate, now);
+                state.tokens.floor() as u64
+            }
+            None => self.rate.capacity as u64,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| lock(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Drops idle keys; meant to be called periodically
+    pub fn evict_idle(&self) {
+        let now = Instant::now();
+        for shard in &self.shards {
+            lock(shard).retain(|_, state| state.idle_for(&self.rate, now).is_none());
+        }
+    }
+
+    // Stores the bucket of a new key. A full shard makes room by dropping an idle
+    // bucket among a few sampled ones; buckets still holding a client back are
+    // never dropped, so with no room the new key is simply not tracked. Its bucket
+    // would start full anyway, and `evict_idle` frees the space later.
+    fn track(&self, shard: &mut HashMap<String, BucketState>, key: &str, state: BucketState, now: Instant) {
+        if shard.len() >= self.max_keys_per_shard {
+            let idle = shard.iter()
+                .take(EVICTION_SAMPLE)
+                .find(|(_, state)| state.idle_for(&self.rate, now).is_some())
+                .map(|(key, _)| key.clone());
+            match idle {
+                Some(idle) => {
+                    shard.remove(&idle);
+                }
+                None => return,
+            }
+        }
+        shard.insert(key.to_string(), state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_basic_rate_limiting() {
+        let bucket = TokenBucket::new(10, 5, Duration::from_secs(1)).unwrap();
+
+        // Exhaust initial tokens
+        for _ in 0..10 {
+            assert!(bucket.try_acquire(1).is_ok());
+        }
+        assert!(bucket.try_acquire(1).is_err());
+
+        // Wait for refill
+        thread::sleep(Duration::from_secs(1));
+        assert!(bucket.try_acquire(5).is_ok());
+        assert!(bucket.try_acquire(1).is_err());
+    }
+
+    #[test]
+    fn test_keyed_buckets_report_wait_time() {
+        let buckets = KeyedTokenBucket::new(2, 1, Duration::from_secs(10)).unwrap();
+
+        assert_eq!(buckets.try_acquire_n("a", 2).unwrap(), 0);
+        match buckets.try_acquire_n("a", 1) {
+            Err(RateLimitError::RetryAfter(wait)) => {
+                assert!(wait > Duration::from_secs(9) && wait <= Duration::from_secs(10));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Other keys are unaffected
+        assert_eq!(buckets.try_acquire_n("b", 1).unwrap(), 1);
+        assert!(matches!(buckets.try_acquire_n("b", 3), Err(RateLimitError::ExceedsCapacity(3))));
+
+        buckets.release("a", 1);
+        assert_eq!(buckets.available("a"), 1);
+    }
+
+    #[test]
+    fn test_debit_overdraws_bucket() {
+        let buckets = KeyedTokenBucket::new(5, 1, Duration::from_secs(10)).unwrap();
+
+        buckets.debit("a", 8);
+        assert_eq!(buckets.available("a"), 0);
+        match buckets.try_acquire_n("a", 1) {
+            // Three tokens owed plus the one requested
+            Err(RateLimitError::RetryAfter(wait)) => assert!(wait > Duration::from_secs(30)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_keyed_buckets_are_bounded() {
+        let buckets = KeyedTokenBucket::new(5, 5, Duration::from_secs(60))
+            .unwrap()
+            .with_max_keys(SHARDS);
+
+        for i in 0..1_000 {
+            buckets.try_acquire_n(&format!("client-{}", i), 1).unwrap();
+        }
+        assert!(buckets.len() <= SHARDS);
+    }
+
+    #[test]
+    fn test_throttled_buckets_survive_key_rotation() {
+        let buckets = KeyedTokenBucket::new(1, 1, Duration::from_secs(60))
+            .unwrap()
+            .with_max_keys(SHARDS);
+
+        buckets.try_acquire_n("victim", 1).unwrap();
+        for i in 0..1_000 {
+            let _ = buckets.try_acquire_n(&format!("rotated-{}", i), 1);
+        }
+        assert!(buckets.try_acquire_n("victim", 1).is_err());
+        assert!(buckets.len() <= SHARDS);
+    }
+
+    #[test]
+    fn test_full_buckets_are_evicted() {
+        let buckets = KeyedTokenBucket::new(1, 1, Duration::from_millis(10)).unwrap();
+        buckets.try_acquire_n("a", 1).unwrap();
+        assert_eq!(buckets.len(), 1);
+
+        thread::sleep(Duration::from_millis(20));
+        buckets.evict_idle();
+        assert!(buckets.is_empty());
+    }
+}
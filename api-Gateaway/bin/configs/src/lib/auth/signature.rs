@@ -0,0 +1,333 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use hyper::HeaderMap;
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use crate::models::config::{SignatureEncoding, SignaturePreset, SignatureVerificationConfig};
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("Missing signature header: {0}")]
+    MissingHeader(String),
+    #[error("Malformed signature header")]
+    MalformedHeader,
+    #[error("Signature timestamp outside tolerance window")]
+    TimestampOutOfTolerance,
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Invalid signing secret")]
+    InvalidSecret,
+}
+
+pub trait SignatureVerifier: Send + Sync {
+    fn verify(&self, headers: &HeaderMap, body: &[u8]) -> Result<(), SignatureError>;
+}
+
+#[derive(Debug, Clone)]
+pub enum HeaderFormat {
+    // `sha256=<sig>` style, the prefix is stripped before decoding
+    Prefixed(String),
+    // `t=<ts>,v1=<sig>,v1=<sig>` style, as sent by Stripe
+    KeyValue {
+        timestamp_key: String,
+        signature_key: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum TimestampSource {
+    Header(String),
+    HeaderField,
+}
+
+#[derive(Debug, Clone)]
+pub struct SignatureScheme {
+    pub header: String,
+    pub format: HeaderFormat,
+    pub timestamp: Option<TimestampSource>,
+    // Bytes signed ahead of the raw body; `{timestamp}` is substituted
+    pub payload_prefix: String,
+    pub encoding: SignatureEncoding,
+    pub tolerance_seconds: u64,
+}
+
+impl SignatureScheme {
+    pub fn stripe() -> Self {
+        Self {
+            header: "Stripe-Signature".into(),
+            format: HeaderFormat::KeyValue {
+                timestamp_key: "t".into(),
+                signature_key: "v1".into(),
+            },
+            timestamp: Some(TimestampSource::HeaderField),
+            payload_prefix: "{timestamp}.".into(),
+            encoding: SignatureEncoding::Hex,
+            tolerance_seconds: 300,
+        }
+    }
+
+    pub fn github() -> Self {
+        Self {
+            header: "X-Hub-Signature-256".into(),
+            format: HeaderFormat::Prefixed("sha256=".into()),
+            timestamp: None,
+            payload_prefix: String::new(),
+            encoding: SignatureEncoding::Hex,
+            tolerance_seconds: 0,
+        }
+    }
+
+    pub fn slack() -> Self {
+        Self {
+            header: "X-Slack-Signature".into(),
+            format: HeaderFormat::Prefixed("v0=".into()),
+            timestamp: Some(TimestampSource::Header("X-Slack-Request-Timestamp".into())),
+            payload_prefix: "v0:{timestamp}:".into(),
+            encoding: SignatureEncoding::Hex,
+            tolerance_seconds: 300,
+        }
+    }
+
+    pub fn custom() -> Self {
+        Self {
+            header: "X-Signature".into(),
+            format: HeaderFormat::Prefixed(String::new()),
+            timestamp: None,
+            payload_prefix: String::new(),
+            encoding: SignatureEncoding::Hex,
+            tolerance_seconds: 300,
+        }
+    }
+
+    pub fn from_config(config: &SignatureVerificationConfig) -> Self {
+        let mut scheme = match config.preset {
+            SignaturePreset::Stripe => Self::stripe(),
+            SignaturePreset::Github => Self::github(),
+            SignaturePreset::Slack => Self::slack(),
+            SignaturePreset::Custom => Self::custom(),
+        };
+
+        if let Some(header) = &config.header {
+            scheme.header = header.clone();
+        }
+        if let Some(prefix) = &config.prefix {
+            scheme.format = HeaderFormat::Prefixed(prefix.clone());
+        }
+        if let (Some(timestamp_key), Some(signature_key)) = (&config.timestamp_key, &config.signature_key) {
+            scheme.format = HeaderFormat::KeyValue {
+                timestamp_key: timestamp_key.clone(),
+                signature_key: signature_key.clone(),
+            };
+            scheme.timestamp = Some(TimestampSource::HeaderField);
+        }
+        if let Some(timestamp_header) = &config.timestamp_header {
+            scheme.timestamp = Some(TimestampSource::Header(timestamp_header.clone()));
+        }
+        if let Some(payload_prefix) = &config.payload_prefix {
+            scheme.payload_prefix = payload_prefix.clone();
+        }
+        if let Some(encoding) = &config.encoding {
+            scheme.encoding = encoding.clone();
+        }
+        if let Some(tolerance) = config.tolerance_seconds {
+            scheme.tolerance_seconds = tolerance;
+        }
+
+        scheme
+    }
+}
+
+pub struct HmacSignatureVerifier {
+    secret: Vec<u8>,
+    scheme: SignatureScheme,
+}
+
+impl HmacSignatureVerifier {
+    pub fn new(secret: impl Into<Vec<u8>>, scheme: SignatureScheme) -> Result<Self, SignatureError> {
+        let secret = secret.into();
+        if secret.is_empty() {
+            return Err(SignatureError::InvalidSecret);
+        }
+
+        Ok(Self { secret, scheme })
+    }
+
+    pub fn from_config(config: &SignatureVerificationConfig) -> Result<Self, SignatureError> {
+        Self::new(config.secret.as_bytes(), SignatureScheme::from_config(config))
+    }
+
+    fn parse_header(&self, value: &str) -> Result<(Option<String>, Vec<Vec<u8>>), SignatureError> {
+        match &self.scheme.format {
+            HeaderFormat::Prefixed(prefix) => {
+                let encoded = value.trim()
+                    .strip_prefix(prefix.as_str())
+                    .ok_or(SignatureError::MalformedHeader)?;
+                Ok((None, vec![self.decode(encoded)?]))
+            }
+            HeaderFormat::KeyValue { timestamp_key, signature_key } => {
+                let mut timestamp = None;
+                let mut signatures = Vec::new();
+
+                for pair in value.split(',') {
+                    let (key, val) = pair.trim()
+                        .split_once('=')
+                        .ok_or(SignatureError::MalformedHeader)?;
+                    if key == timestamp_key {
+                        timestamp = Some(val.to_string());
+                    } else if key == signature_key {
+                        // Unknown or undecodable entries are skipped, e.g. during secret rotation
+                        if let Ok(sig) = self.decode(val) {
+                            signatures.push(sig);
+                        }
+                    }
+                }
+
+                if signatures.is_empty() {
+                    return Err(SignatureError::MalformedHeader);
+                }
+                Ok((timestamp, signatures))
+            }
+        }
+    }
+
+    fn decode(&self, encoded: &str) -> Result<Vec<u8>, SignatureError> {
+        let decoded = match self.scheme.encoding {
+            SignatureEncoding::Hex => hex::decode(encoded).ok(),
+            SignatureEncoding::Base64 => BASE64.decode(encoded).ok(),
+        };
+        decoded.ok_or(SignatureError::MalformedHeader)
+    }
+
+    fn check_timestamp(&self, timestamp: &str) -> Result<(), SignatureError> {
+        let timestamp: u64 = timestamp.trim()
+            .parse()
+            .map_err(|_| SignatureError::MalformedHeader)?;
+
+        if self.scheme.tolerance_seconds == 0 {
+            return Ok(());
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        if now.abs_diff(timestamp) > self.scheme.tolerance_seconds {
+            return Err(SignatureError::TimestampOutOfTolerance);
+        }
+        Ok(())
+    }
+}
+
+impl SignatureVerifier for HmacSignatureVerifier {
+    fn verify(&self, headers: &HeaderMap, body: &[u8]) -> Result<(), SignatureError> {
+        let header_value = headers.get(self.scheme.header.as_str())
+            .and_then(|h| h.to_str().ok())
+            .ok_or_else(|| SignatureError::MissingHeader(self.scheme.header.clone()))?;
+
+        let (field_timestamp, signatures) = self.parse_header(header_value)?;
+
+        let timestamp = match &self.scheme.timestamp {
+            None => None,
+            Some(TimestampSource::HeaderField) => {
+                Some(field_timestamp.ok_or(SignatureError::MalformedHeader)?)
+            }
+            Some(TimestampSource::Header(name)) => Some(
+                headers.get(name.as_str())
+                    .and_then(|h| h.to_str().ok())
+                    .map(|s| s.to_string())
+                    .ok_or_else(|| SignatureError::MissingHeader(name.clone()))?,
+            ),
+        };
+
+        if let Some(ts) = &timestamp {
+            self.check_timestamp(ts)?;
+        }
+
+        let prefix = self.scheme.payload_prefix
+            .replace("{timestamp}", timestamp.as_deref().unwrap_or_default());
+
+        let mut mac = HmacSha256::new_from_slice(&self.secret)
+            .map_err(|_| SignatureError::InvalidSecret)?;
+        mac.update(prefix.as_bytes());
+        mac.update(body);
+
+        // verify_slice compares in constant time
+        if signatures.iter().any(|sig| mac.clone().verify_slice(sig).is_ok()) {
+            Ok(())
+        } else {
+            Err(SignatureError::InvalidSignature)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "whsec_test_secret";
+
+    fn sign(payload: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(payload);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn test_stripe_signature() {
+        let verifier = HmacSignatureVerifier::new(SECRET, SignatureScheme::stripe()).unwrap();
+        let body = br#"{"id":"evt_1"}"#;
+        let ts = now();
+        let sig = sign(format!("{}.{}", ts, std::str::from_utf8(body).unwrap()).as_bytes());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Stripe-Signature", format!("t={},v1={}", ts, sig).parse().unwrap());
+        assert!(verifier.verify(&headers, body).is_ok());
+
+        headers.insert("Stripe-Signature", format!("t={},v1={}", ts - 600, sig).parse().unwrap());
+        assert!(matches!(
+            verifier.verify(&headers, body),
+            Err(SignatureError::TimestampOutOfTolerance)
+        ));
+    }
+
+    #[test]
+    fn test_github_signature() {
+        let verifier = HmacSignatureVerifier::new(SECRET, SignatureScheme::github()).unwrap();
+        let body = b"payload";
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Hub-Signature-256", format!("sha256={}", sign(body)).parse().unwrap());
+        assert!(verifier.verify(&headers, body).is_ok());
+        assert!(matches!(
+            verifier.verify(&headers, b"tampered"),
+            Err(SignatureError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_slack_signature() {
+        let verifier = HmacSignatureVerifier::new(SECRET, SignatureScheme::slack()).unwrap();
+        let body = b"token=abc&team_id=T1";
+        let ts = now();
+        let sig = sign(format!("v0:{}:{}", ts, std::str::from_utf8(body).unwrap()).as_bytes());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Slack-Signature", format!("v0={}", sig).parse().unwrap());
+        headers.insert("X-Slack-Request-Timestamp", ts.to_string().parse().unwrap());
+        assert!(verifier.verify(&headers, body).is_ok());
+
+        headers.remove("X-Slack-Request-Timestamp");
+        assert!(matches!(
+            verifier.verify(&headers, body),
+            Err(SignatureError::MissingHeader(_))
+        ));
+    }
+}
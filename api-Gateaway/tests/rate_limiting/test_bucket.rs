@@ -21,7 +21,7 @@ fn test_token_bucket_refill() {
 async fn test_redis_rate_limiting() {
     use crate::rate_limiting::redis_store::{RedisRateLimiter, RateLimitConfig};
     
-    let limiter = RedisRateLimiter::new("redis://localhost:6379").unwrap();
+    let limiter = RedisRateLimiter::new("redis://localhost:6379").await.unwrap();
     let test_key = format!("test:{}", uuid::Uuid::new_v4());
     let config = RateLimitConfig {
         capacity: 5,
@@ -45,4 +45,78 @@ async fn test_redis_rate_limiting() {
     let (allowed, remaining) = limiter.check_rate_limit(&test_key, &config).await.unwrap();
     assert!(allowed);
     assert_eq!(remaining, 1);
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_redis_window_algorithms() {
+    use crate::models::config::RateLimitAlgorithm;
+    use crate::rate_limiting::redis_store::{RedisRateLimiter, RateLimitConfig};
+
+    let limiter = RedisRateLimiter::new("redis://localhost:6379").await.unwrap();
+    let config = RateLimitConfig {
+        capacity: 3,
+        refill_amount: 3,
+        refill_seconds: 60,
+        burst: 0,
+    };
+
+    for algorithm in [
+        RateLimitAlgorithm::FixedWindow,
+        RateLimitAlgorithm::SlidingWindow,
+        RateLimitAlgorithm::SlidingWindowLog,
+        RateLimitAlgorithm::Gcra,
+    ] {
+        let test_key = format!("test:{}", uuid::Uuid::new_v4());
+
+        for _ in 0..3 {
+            let (allowed, _, _) = limiter.check(&test_key, &config, &algorithm).await.unwrap();
+            assert!(allowed, "{:?}", algorithm);
+        }
+
+        let (allowed, remaining, reset) = limiter.check(&test_key, &config, &algorithm).await.unwrap();
+        assert!(!allowed, "{:?}", algorithm);
+        assert_eq!(remaining, 0);
+        assert!(reset > 0 && reset <= 60, "{:?} reset {}", algorithm, reset);
+    }
+}
+
+#[tokio::test]
+async fn test_redis_request_cost() {
+    use crate::models::config::RateLimitAlgorithm;
+    use crate::rate_limiting::redis_store::{RedisRateLimiter, RateLimitConfig};
+
+    let limiter = RedisRateLimiter::new("redis://localhost:6379").await.unwrap();
+    let config = RateLimitConfig {
+        capacity: 10,
+        refill_amount: 10,
+        refill_seconds: 60,
+        burst: 0,
+    };
+
+    for algorithm in [
+        RateLimitAlgorithm::TokenBucket,
+        RateLimitAlgorithm::FixedWindow,
+        RateLimitAlgorithm::SlidingWindow,
+        RateLimitAlgorithm::SlidingWindowLog,
+        RateLimitAlgorithm::Gcra,
+    ] {
+        let test_key = format!("test:{}", uuid::Uuid::new_v4());
+
+        for _ in 0..2 {
+            let (allowed, _, _) = limiter.check_n(&test_key, &config, &algorithm, 4).await.unwrap();
+            assert!(allowed, "{:?}", algorithm);
+        }
+
+        // Not enough left for another expensive request, but a cheaper one fits
+        let (allowed, _, _) = limiter.check_n(&test_key, &config, &algorithm, 4).await.unwrap();
+        assert!(!allowed, "{:?}", algorithm);
+        let (allowed, _, _) = limiter.check_n(&test_key, &config, &algorithm, 2).await.unwrap();
+        assert!(allowed, "{:?}", algorithm);
+
+        // Debits are never refused and push the key into overdraft
+        let (allowed, _, _) = limiter.debit(&test_key, &config, &algorithm, 5).await.unwrap();
+        assert!(allowed, "{:?}", algorithm);
+        let (allowed, _, _) = limiter.check(&test_key, &config, &algorithm).await.unwrap();
+        assert!(!allowed, "{:?}", algorithm);
+    }
+}
@@ -11,6 +11,7 @@ fn test_route_matching() {
             rewrite: None,
             prefix: false,
             regex: None,
+            ..Default::default()
         },
         Route {
             path: "/products".into(),
@@ -19,6 +20,7 @@ fn test_route_matching() {
             rewrite: None,
             prefix: true,
             regex: None,
+            ..Default::default()
         },
     ];
 